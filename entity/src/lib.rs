@@ -2,4 +2,9 @@
 
 pub mod prelude;
 
+pub mod channel_setting;
+pub mod guild_setting;
+pub mod hidden_roll;
 pub mod nist_rand_entry;
+pub mod roll_log;
+pub mod roll_macro;
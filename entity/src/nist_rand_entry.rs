@@ -5,6 +5,8 @@ use sea_orm::entity::prelude::*;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "nist_rand_entry")]
 pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub provider: String,
     #[sea_orm(primary_key, auto_increment = false)]
     pub chain_index: i32,
     #[sea_orm(primary_key, auto_increment = false)]
@@ -13,6 +15,33 @@ pub struct Model {
     pub timestamp: String,
     #[sea_orm(column_type = "Binary(BlobSize::Blob(None))")]
     pub output_value: Vec<u8>,
+    pub version: Option<String>,
+    pub cipher_suite: Option<i32>,
+    pub period: Option<i32>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub certificate_id: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub local_random_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub external_source_id: Option<Vec<u8>>,
+    pub external_status_code: Option<i32>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub external_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub previous_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub hour_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub day_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub month_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub year_value: Option<Vec<u8>>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub precommitment_value: Option<Vec<u8>>,
+    pub status_code: Option<i32>,
+    #[sea_orm(column_type = "Binary(BlobSize::Blob(None))", nullable)]
+    pub signature_value: Option<Vec<u8>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
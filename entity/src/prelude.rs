@@ -1,3 +1,8 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+pub use super::channel_setting::Entity as ChannelSetting;
+pub use super::guild_setting::Entity as GuildSetting;
+pub use super::hidden_roll::Entity as HiddenRoll;
 pub use super::nist_rand_entry::Entity as NistRandEntry;
+pub use super::roll_log::Entity as RollLog;
+pub use super::roll_macro::Entity as RollMacro;
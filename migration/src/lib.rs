@@ -1,12 +1,30 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261017_000001_create_roll_visibility;
+mod m20261017_000002_create_roll_log;
+mod m20261017_000003_add_roll_draws;
+mod m20261017_000004_create_roll_macro;
+mod m20261017_000005_add_random_source;
+mod m20261017_000006_add_pulse_fields;
+mod m20261017_000007_add_beacon_provider;
+mod m20261017_000008_add_roll_gm;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261017_000001_create_roll_visibility::Migration),
+            Box::new(m20261017_000002_create_roll_log::Migration),
+            Box::new(m20261017_000003_add_roll_draws::Migration),
+            Box::new(m20261017_000004_create_roll_macro::Migration),
+            Box::new(m20261017_000005_add_random_source::Migration),
+            Box::new(m20261017_000006_add_pulse_fields::Migration),
+            Box::new(m20261017_000007_add_beacon_provider::Migration),
+            Box::new(m20261017_000008_add_roll_gm::Migration),
+        ]
     }
 }
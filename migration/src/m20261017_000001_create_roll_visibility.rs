@@ -0,0 +1,94 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChannelSetting::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChannelSetting::ChannelId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ChannelSetting::RollVisibility).string())
+                    .col(ColumnDef::new(ChannelSetting::GmId).big_integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(HiddenRoll::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(HiddenRoll::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(HiddenRoll::GuildId).big_integer())
+                    .col(
+                        ColumnDef::new(HiddenRoll::ChannelId)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(HiddenRoll::UserId).big_integer().not_null())
+                    .col(ColumnDef::new(HiddenRoll::GmId).big_integer())
+                    .col(ColumnDef::new(HiddenRoll::Visibility).string().not_null())
+                    .col(ColumnDef::new(HiddenRoll::Title).string().not_null())
+                    .col(ColumnDef::new(HiddenRoll::Description).text().not_null())
+                    .col(ColumnDef::new(HiddenRoll::Colour).integer().not_null())
+                    .col(ColumnDef::new(HiddenRoll::CreatedAt).date_time().not_null())
+                    .col(
+                        ColumnDef::new(HiddenRoll::Revealed)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(HiddenRoll::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ChannelSetting::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ChannelSetting {
+    Table,
+    ChannelId,
+    RollVisibility,
+    GmId,
+}
+
+#[derive(DeriveIden)]
+enum HiddenRoll {
+    Table,
+    Id,
+    GuildId,
+    ChannelId,
+    UserId,
+    GmId,
+    Visibility,
+    Title,
+    Description,
+    Colour,
+    CreatedAt,
+    Revealed,
+}
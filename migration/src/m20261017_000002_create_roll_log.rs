@@ -0,0 +1,66 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RollLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RollLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RollLog::GuildId).big_integer())
+                    .col(ColumnDef::new(RollLog::ChannelId).big_integer().not_null())
+                    .col(ColumnDef::new(RollLog::UserId).big_integer().not_null())
+                    .col(ColumnDef::new(RollLog::Visibility).string().not_null())
+                    .col(ColumnDef::new(RollLog::Notation).string().not_null())
+                    .col(ColumnDef::new(RollLog::Label).string())
+                    .col(ColumnDef::new(RollLog::Dice).text().not_null())
+                    .col(ColumnDef::new(RollLog::Total).big_integer().not_null())
+                    .col(ColumnDef::new(RollLog::CreatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("roll-log-channel-created-at")
+                    .table(RollLog::Table)
+                    .col(RollLog::ChannelId)
+                    .col(RollLog::CreatedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RollLog::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RollLog {
+    Table,
+    Id,
+    GuildId,
+    ChannelId,
+    UserId,
+    Visibility,
+    Notation,
+    Label,
+    Dice,
+    Total,
+    CreatedAt,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RollLog::Table)
+                    .add_column(ColumnDef::new(RollLog::Draws).text())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(HiddenRoll::Table)
+                    .add_column(ColumnDef::new(HiddenRoll::Footer).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(HiddenRoll::Table)
+                    .drop_column(HiddenRoll::Footer)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RollLog::Table)
+                    .drop_column(RollLog::Draws)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RollLog {
+    Table,
+    Draws,
+}
+
+#[derive(DeriveIden)]
+enum HiddenRoll {
+    Table,
+    Footer,
+}
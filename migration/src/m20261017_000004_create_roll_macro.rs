@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RollMacro::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RollMacro::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RollMacro::UserId).big_integer().not_null())
+                    .col(ColumnDef::new(RollMacro::GuildId).big_integer())
+                    .col(ColumnDef::new(RollMacro::Name).string().not_null())
+                    .col(ColumnDef::new(RollMacro::Notation).string().not_null())
+                    .col(ColumnDef::new(RollMacro::CreatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("roll-macro-user-guild-name")
+                    .table(RollMacro::Table)
+                    .col(RollMacro::UserId)
+                    .col(RollMacro::GuildId)
+                    .col(RollMacro::Name)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RollMacro::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RollMacro {
+    Table,
+    Id,
+    UserId,
+    GuildId,
+    Name,
+    Notation,
+    CreatedAt,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(GuildSetting::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(GuildSetting::GuildId)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(GuildSetting::RandomSource).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RollLog::Table)
+                    .add_column(ColumnDef::new(RollLog::Source).string())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RollLog::Table)
+                    .drop_column(RollLog::Source)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GuildSetting::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum GuildSetting {
+    Table,
+    GuildId,
+    RandomSource,
+}
+
+#[derive(DeriveIden)]
+enum RollLog {
+    Table,
+    Source,
+}
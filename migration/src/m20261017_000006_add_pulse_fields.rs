@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// The rest of a beacon pulse, null for pulses stored before it was kept
+fn columns() -> Vec<ColumnDef> {
+    use NistRandEntry::*;
+    let mut columns = vec![ColumnDef::new(Version).string().to_owned()];
+    for col in [CipherSuite, Period, ExternalStatusCode, StatusCode] {
+        columns.push(ColumnDef::new(col).integer().to_owned());
+    }
+    for col in [
+        CertificateId,
+        LocalRandomValue,
+        ExternalSourceId,
+        ExternalValue,
+        PreviousValue,
+        HourValue,
+        DayValue,
+        MonthValue,
+        YearValue,
+        PrecommitmentValue,
+        SignatureValue,
+    ] {
+        columns.push(ColumnDef::new(col).binary().to_owned());
+    }
+    columns
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only adds one column per statement
+        for mut col in columns() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(NistRandEntry::Table)
+                        .add_column(&mut col)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for col in columns().into_iter().rev() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(NistRandEntry::Table)
+                        .drop_column(Alias::new(col.get_column_name()))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum NistRandEntry {
+    Table,
+    Version,
+    CipherSuite,
+    Period,
+    CertificateId,
+    LocalRandomValue,
+    ExternalSourceId,
+    ExternalStatusCode,
+    ExternalValue,
+    PreviousValue,
+    HourValue,
+    DayValue,
+    MonthValue,
+    YearValue,
+    PrecommitmentValue,
+    StatusCode,
+    SignatureValue,
+}
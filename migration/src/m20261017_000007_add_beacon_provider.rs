@@ -0,0 +1,147 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Every column but `provider`, in the order the table is rebuilt with
+const COLUMNS: [NistRandEntry; 21] = {
+    use NistRandEntry::*;
+    [
+        ChainIndex,
+        PulseIndex,
+        Uri,
+        Timestamp,
+        OutputValue,
+        Version,
+        CipherSuite,
+        Period,
+        CertificateId,
+        LocalRandomValue,
+        ExternalSourceId,
+        ExternalStatusCode,
+        ExternalValue,
+        PreviousValue,
+        HourValue,
+        DayValue,
+        MonthValue,
+        YearValue,
+        PrecommitmentValue,
+        StatusCode,
+        SignatureValue,
+    ]
+};
+
+/// The pulse table, keyed by `provider` too if `with_provider`.
+fn create(table: NistRandEntry, with_provider: bool) -> TableCreateStatement {
+    let mut create = Table::create();
+    create.table(table);
+    let mut primary = Index::create();
+    primary.primary().name("nist-rand-entry-primary");
+    if with_provider {
+        create.col(
+            ColumnDef::new(NistRandEntry::Provider)
+                .string()
+                .not_null()
+                .default("nist"),
+        );
+        primary.col(NistRandEntry::Provider);
+    }
+    for col in COLUMNS {
+        let mut def = ColumnDef::new(col);
+        match col {
+            NistRandEntry::ChainIndex => def.integer().not_null(),
+            NistRandEntry::PulseIndex => def.big_integer().not_null(),
+            NistRandEntry::Uri => def.string().not_null(),
+            NistRandEntry::Timestamp => def.date_time().not_null(),
+            NistRandEntry::OutputValue => def.binary_len(512).not_null(),
+            NistRandEntry::Version => def.string(),
+            NistRandEntry::CipherSuite
+            | NistRandEntry::Period
+            | NistRandEntry::ExternalStatusCode
+            | NistRandEntry::StatusCode => def.integer(),
+            _ => def.binary(),
+        };
+        create.col(&mut def);
+    }
+    create
+        .index(
+            primary
+                .col(NistRandEntry::ChainIndex)
+                .col(NistRandEntry::PulseIndex),
+        )
+        .to_owned()
+}
+
+/// SQLite can't change a primary key, so the table is copied into a new one.
+async fn rebuild(manager: &SchemaManager<'_>, with_provider: bool) -> Result<(), DbErr> {
+    manager
+        .create_table(create(NistRandEntry::Next, with_provider))
+        .await?;
+    let columns = COLUMNS
+        .iter()
+        .map(|col| col.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let copy = if with_provider {
+        format!(
+            "INSERT INTO nist_rand_entry_next (provider, {columns}) \
+             SELECT 'nist', {columns} FROM nist_rand_entry"
+        )
+    } else {
+        format!(
+            "INSERT INTO nist_rand_entry_next ({columns}) \
+             SELECT {columns} FROM nist_rand_entry WHERE provider = 'nist'"
+        )
+    };
+    manager.get_connection().execute_unprepared(&copy).await?;
+    manager
+        .drop_table(Table::drop().table(NistRandEntry::Table).to_owned())
+        .await?;
+    manager
+        .rename_table(
+            Table::rename()
+                .table(NistRandEntry::Next, NistRandEntry::Table)
+                .to_owned(),
+        )
+        .await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rebuild(manager, true).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rebuild(manager, false).await
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum NistRandEntry {
+    Table,
+    #[sea_orm(iden = "nist_rand_entry_next")]
+    Next,
+    Provider,
+    ChainIndex,
+    PulseIndex,
+    Uri,
+    Timestamp,
+    OutputValue,
+    Version,
+    CipherSuite,
+    Period,
+    CertificateId,
+    LocalRandomValue,
+    ExternalSourceId,
+    ExternalStatusCode,
+    ExternalValue,
+    PreviousValue,
+    HourValue,
+    DayValue,
+    MonthValue,
+    YearValue,
+    PrecommitmentValue,
+    StatusCode,
+    SignatureValue,
+}
@@ -0,0 +1,178 @@
+use entity::nist_rand_entry;
+use poise::{serenity_prelude::CreateEmbed, CreateReply};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::repo::{
+    beacon::Provider,
+    nist_beacon::{BeaconStatus, PulseId},
+};
+
+use super::{roll::embed_error, Context, Result};
+
+/// Inspect the randomness beacons rolls draw from
+#[poise::command(slash_command, subcommands("status", "pulse"), subcommand_required)]
+pub async fn beacon(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Show the stored pulses, the reserve and recent fetch errors
+#[poise::command(slash_command)]
+async fn status(ctx: Context<'_>) -> Result<()> {
+    let repo = &ctx.data().nist_repo;
+    let mut latest = vec![];
+    for provider in repo.providers() {
+        latest.push((provider, repo.latest_stored(provider).await?));
+    }
+    let stored = repo.stored_count().await?;
+    let text = format_status(&latest, stored, &repo.status());
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("Beacon status")
+                    .description(text),
+            )
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Show the output of a stored pulse
+#[poise::command(slash_command)]
+async fn pulse(
+    ctx: Context<'_>,
+    #[description = "Chain index"] chain: i32,
+    #[description = "Pulse index, the round for drand"] pulse: i64,
+    #[description = "Beacon the pulse is from, defaults to NIST"] provider: Option<Provider>,
+) -> Result<()> {
+    let id = PulseId {
+        provider: provider.unwrap_or_default(),
+        chain_index: chain,
+        pulse_index: pulse,
+    };
+    let Some(entry) = ctx.data().nist_repo.stored_pulse(id).await? else {
+        ctx.send(embed_error(
+            "Pulse not found",
+            format!(
+                "No {} pulse {chain}/{pulse} is stored, only pulses that were fetched for rolls are.",
+                id.provider.name()
+            ),
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    let text = format!(
+        "Published {}\n```\n{}\n```\n[View at the beacon]({})",
+        format_time(&entry.timestamp),
+        hex(&entry.output_value),
+        entry.uri,
+    );
+    ctx.send(
+        CreateReply::default().embed(
+            CreateEmbed::default()
+                .title(format!("{} pulse {chain}/{pulse}", id.provider.name()))
+                .description(text),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A stored timestamp as a Discord relative time, or as is if it does not
+/// parse.
+fn format_time(timestamp: &str) -> String {
+    match OffsetDateTime::parse(timestamp, &Rfc3339) {
+        Ok(t) => format!("<t:{}:R>", t.unix_timestamp()),
+        Err(_) => format!("at {timestamp}"),
+    }
+}
+
+fn format_status(
+    latest: &[(Provider, Option<nist_rand_entry::Model>)],
+    stored: u64,
+    status: &BeaconStatus,
+) -> String {
+    let mut lines = vec![];
+    for (provider, entry) in latest {
+        lines.push(match entry {
+            Some(e) => format!(
+                "**{}**: latest pulse {}/{}, published {}",
+                provider.name(),
+                e.chain_index,
+                e.pulse_index,
+                format_time(&e.timestamp),
+            ),
+            None => format!("**{}**: no pulse stored yet", provider.name()),
+        });
+    }
+    lines.push(format!("Pulses stored: {stored}"));
+
+    let queued = match status.queued_bits {
+        Some(bits) => format!("{bits} bit(s) queued"),
+        None => "queue in use by a roll".into(),
+    };
+    lines.push(format!(
+        "Reserve: {}/{} pulse(s), {queued}",
+        status.reserve, status.target
+    ));
+    if let Some(t) = status.next_pulse {
+        lines.push(format!("Next pulse due <t:{}:R>", t.unix_timestamp()));
+    }
+    lines.push(format!(
+        "Since start: {} bit(s) used, {} pulse(s) fetched, {} roll(s) waited, {} in line now",
+        status.bits_consumed, status.pulses_fetched, status.waits, status.waiting
+    ));
+
+    lines.push(format!("Fetch errors: {}", status.fetch_errors));
+    if let Some((t, e)) = &status.last_error {
+        lines.push(format!(
+            "Last error <t:{}:R>: `{}`",
+            t.unix_timestamp(),
+            e.replace('`', "'")
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use crate::repo::{beacon::Provider, nist_beacon::BeaconStatus};
+
+    use super::{format_status, format_time, hex};
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(hex(&[0x00, 0xab, 0x10]), "00ab10");
+        assert_eq!(format_time("2024-05-01T12:00:00.000Z"), "<t:1714564800:R>");
+        assert_eq!(format_time("yesterday"), "at yesterday");
+
+        let status = BeaconStatus {
+            reserve: 1,
+            target: 4,
+            waiting: 0,
+            next_pulse: None,
+            bits_consumed: 12,
+            pulses_fetched: 3,
+            waits: 1,
+            fetch_errors: 2,
+            last_error: Some((OffsetDateTime::UNIX_EPOCH, "NIST: `timeout`".into())),
+            queued_bits: None,
+        };
+        let text = format_status(&[(Provider::Nist, None)], 7, &status);
+        assert!(text.contains("**NIST**: no pulse stored yet"));
+        assert!(text.contains("Pulses stored: 7"));
+        assert!(text.contains("queue in use by a roll"));
+        assert!(text.ends_with("Last error <t:0:R>: `NIST: 'timeout'`"));
+    }
+}
@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+use entity::roll_log;
+use poise::{
+    serenity_prelude::{
+        self as serenity, ButtonStyle, CreateActionRow, CreateAttachment, CreateButton,
+        CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    CreateReply,
+};
+use time::{format_description::well_known::Rfc3339, Date, OffsetDateTime};
+
+use crate::repo::roll::RollLogFilter;
+
+use super::{roll::embed_error, Context, Result};
+
+const PER_PAGE: u64 = 10;
+/// Most rolls an export may hold, to stay under Discord's attachment limit
+const MAX_EXPORT: u64 = 10_000;
+/// How long the page buttons keep working
+const PAGE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ExportFormat {
+    #[name = "CSV"]
+    Csv,
+    #[name = "JSON"]
+    Json,
+}
+
+/// Look up past rolls in this channel
+#[poise::command(slash_command)]
+pub async fn history(
+    ctx: Context<'_>,
+    #[description = "Only rolls by this user"] user: Option<serenity::User>,
+    #[description = "Only rolls whose notation contains this"] notation: Option<String>,
+    #[description = "From, e.g. 2024-05-01 or 3d for three days ago"] since: Option<String>,
+    #[description = "Until, e.g. 2024-05-31 or 12h for twelve hours ago"] until: Option<String>,
+    #[description = "Search the whole server instead of this channel"] server: Option<bool>,
+    #[description = "Send every matching roll as a file"] export: Option<ExportFormat>,
+) -> Result<()> {
+    let now = OffsetDateTime::now_utc();
+    let parse = |s: Option<String>, end| s.map(|s| parse_time(&s, now, end).ok_or(s)).transpose();
+    let (since, until) = match (parse(since, false), parse(until, true)) {
+        (Ok(since), Ok(until)) => (since, until),
+        (Err(s), _) | (_, Err(s)) => {
+            ctx.send(embed_error(
+                "Could not read time",
+                format!("`{s}` is neither a date like `2024-05-01` nor an age like `3d`."),
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let server = server.unwrap_or(false) && ctx.guild_id().is_some();
+    let filter = RollLogFilter {
+        guild_id: ctx.guild_id().map(|id| id.get()),
+        channel_id: (!server).then(|| ctx.channel_id().get()),
+        user_id: user.map(|u| u.id.get()),
+        notation,
+        since,
+        until,
+        viewer_id: ctx.author().id.get(),
+    };
+    let repo = &ctx.data().roll_repo;
+
+    if let Some(format) = export {
+        let rolls = repo.roll_log(&filter, MAX_EXPORT).await?;
+        let (data, name) = match format {
+            ExportFormat::Csv => (export_csv(&rolls), "history.csv"),
+            ExportFormat::Json => (export_json(&rolls), "history.json"),
+        };
+        ctx.send(
+            CreateReply::default()
+                .content(format!("{} roll(s)", rolls.len()))
+                .attachment(CreateAttachment::bytes(data, name))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Hidden rolls of the caller may be listed, so only they see the pages
+    let (rolls, n) = repo.roll_log_page(&filter, 0, PER_PAGE).await?;
+    let pages = n.div_ceil(PER_PAGE).max(1);
+    if pages == 1 {
+        ctx.send(
+            CreateReply::default()
+                .embed(embed_page(&rolls, 0, pages, n))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let ctx_id = ctx.id();
+    let prev_id = format!("{ctx_id}prev");
+    let next_id = format!("{ctx_id}next");
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&prev_id)
+            .emoji('◀')
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(&next_id)
+            .emoji('▶')
+            .style(ButtonStyle::Secondary),
+    ]);
+    ctx.send(
+        CreateReply::default()
+            .embed(embed_page(&rolls, 0, pages, n))
+            .components(vec![buttons])
+            .ephemeral(true),
+    )
+    .await?;
+
+    let mut page = 0;
+    while let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGE_TIMEOUT)
+        .await
+    {
+        page = if press.data.custom_id == next_id {
+            (page + 1) % pages
+        } else if press.data.custom_id == prev_id {
+            page.checked_sub(1).unwrap_or(pages - 1)
+        } else {
+            continue;
+        };
+
+        let (rolls, n) = repo.roll_log_page(&filter, page, PER_PAGE).await?;
+        press
+            .create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().embed(embed_page(
+                        &rolls,
+                        page,
+                        n.div_ceil(PER_PAGE).max(1),
+                        n,
+                    )),
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn embed_page(rolls: &[roll_log::Model], page: u64, pages: u64, n: u64) -> CreateEmbed {
+    let text = if rolls.is_empty() {
+        "No rolls found.".into()
+    } else {
+        rolls
+            .iter()
+            .map(format_log_line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    CreateEmbed::default()
+        .title("Roll history")
+        .description(text)
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{pages} · {n} roll(s)",
+            page + 1
+        )))
+}
+
+fn format_log_line(r: &roll_log::Model) -> String {
+    let when = OffsetDateTime::parse(&r.created_at, &Rfc3339).map_or_else(
+        |_| r.created_at.clone(),
+        |t| format!("<t:{}:f>", t.unix_timestamp()),
+    );
+    let hidden = if r.visibility == "public" {
+        ""
+    } else {
+        " 🔒"
+    };
+    let label = r
+        .label
+        .as_ref()
+        .map(|l| format!(" ({l})"))
+        .unwrap_or_default();
+    format!(
+        "{when} <@{}> `{}`{label} → **{}**{hidden}",
+        r.user_id, r.notation, r.total
+    )
+}
+
+/// Parse a date (`2024-05-01`), an RFC 3339 timestamp or an age such as
+/// `3d` (minutes, hours, days or weeks ago).
+///
+/// A bare date used as the `end` of a range includes that whole day.
+fn parse_time(s: &str, now: OffsetDateTime, end: bool) -> Option<OffsetDateTime> {
+    let s = s.trim();
+    if let Ok(t) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Some(t);
+    }
+
+    let date_format = time::format_description::parse("[year]-[month]-[day]").ok()?;
+    if let Ok(date) = Date::parse(s, &date_format) {
+        let date = if end { date.next_day()? } else { date };
+        return Some(date.midnight().assume_utc());
+    }
+
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().ok()?;
+    let unit_secs = match unit.trim() {
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    now.checked_sub(time::Duration::seconds(n.checked_mul(unit_secs)?))
+}
+
+fn export_csv(rolls: &[roll_log::Model]) -> Vec<u8> {
+    let mut csv = String::from(
+        "id,created_at,guild_id,channel_id,user_id,visibility,notation,label,dice,total\n",
+    );
+    for r in rolls {
+        let fields = [
+            r.id.to_string(),
+            r.created_at.clone(),
+            r.guild_id.map(|id| id.to_string()).unwrap_or_default(),
+            r.channel_id.to_string(),
+            r.user_id.to_string(),
+            r.visibility.clone(),
+            r.notation.clone(),
+            r.label.clone().unwrap_or_default(),
+            r.dice.clone(),
+            r.total.to_string(),
+        ];
+        let fields: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv.into_bytes()
+}
+
+/// Quote a field if it holds a separator, quote or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.into()
+    }
+}
+
+fn export_json(rolls: &[roll_log::Model]) -> Vec<u8> {
+    let rolls: Vec<_> = rolls
+        .iter()
+        .map(|r| {
+            serde_json::json!({
+                "id": r.id,
+                "created_at": r.created_at,
+                // Discord ids overflow JavaScript numbers
+                "guild_id": r.guild_id.map(|id| id.to_string()),
+                "channel_id": r.channel_id.to_string(),
+                "user_id": r.user_id.to_string(),
+                "visibility": r.visibility,
+                "notation": r.notation,
+                "label": r.label,
+                "dice": serde_json::from_str::<serde_json::Value>(&r.dice)
+                    .unwrap_or(serde_json::Value::Null),
+                "total": r.total,
+            })
+        })
+        .collect();
+    serde_json::to_vec_pretty(&rolls).expect("JSON values always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+
+    use super::{csv_field, parse_time};
+
+    #[test]
+    fn test_parse_time() {
+        let now = datetime!(2024-05-10 12:00 UTC);
+        assert_eq!(
+            parse_time("2024-05-01", now, false),
+            Some(datetime!(2024-05-01 0:00 UTC))
+        );
+        assert_eq!(
+            parse_time("2024-05-01", now, true),
+            Some(datetime!(2024-05-02 0:00 UTC))
+        );
+        assert_eq!(parse_time("3d", now, false), Some(now - Duration::days(3)));
+        assert_eq!(
+            parse_time("12h", now, true),
+            Some(now - Duration::hours(12))
+        );
+        assert_eq!(
+            parse_time("2024-05-01T10:00:00+02:00", now, false),
+            Some(datetime!(2024-05-01 8:00 UTC))
+        );
+        assert_eq!(parse_time("yesterday", now, false), None);
+        assert_eq!(parse_time("3y", now, false), None);
+        assert_eq!(parse_time("99999999999999999w", now, false), None);
+        assert_eq!(parse_time("9999999999d", now, false), None);
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("1d20"), "1d20");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(
+            csv_field(r#"[{"dice":"1d6"}]"#),
+            r#""[{""dice"":""1d6""}]""#
+        );
+    }
+}
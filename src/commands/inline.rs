@@ -0,0 +1,159 @@
+use poise::serenity_prelude::{
+    self as serenity, CreateAllowedMentions, CreateEmbed, CreateEmbedFooter, CreateMessage, Message,
+};
+
+use crate::dice::{self, DiceErr, Macros};
+
+use super::{
+    roll::{
+        format_roll_ids, format_row, roll_batch, roll_colour, row_logs, source_kind, truncate,
+        COLOUR_ERROR, WAIT_NOTICE,
+    },
+    roll_macro::user_macros,
+    visibility::Visibility,
+    Data, Result,
+};
+
+/// Most inline rolls answered per message
+const MAX_INLINE: usize = 5;
+
+/// The notations between `[[` and `]]` in a message, in order, skipping
+/// brackets around anything else such as wiki links.
+fn find_inline(content: &str) -> Vec<&str> {
+    let mut found = vec![];
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let notation = rest[start + 2..start + 2 + len].trim();
+        if dice::looks_like_dice(notation) {
+            found.push(notation);
+        }
+        rest = &rest[start + 2 + len + 2..];
+        if found.len() == MAX_INLINE {
+            break;
+        }
+    }
+    found
+}
+
+/// Roll every `[[notation]]` of a chat message, answering with one compact
+/// embed. Inline rolls are always public, like the message they are in.
+pub async fn handle_message(ctx: &serenity::Context, data: &Data, msg: &Message) -> Result<()> {
+    if msg.author.bot {
+        return Ok(());
+    }
+    let notations = find_inline(&msg.content);
+    if notations.is_empty() {
+        return Ok(());
+    }
+
+    let user_id = msg.author.id.get();
+    let guild_id = msg.guild_id.map(|id| id.get());
+    let macros = if notations.iter().any(|n| n.contains('$')) {
+        user_macros(&data.macro_repo, user_id, guild_id).await?
+    } else {
+        Macros::new()
+    };
+    let mut batches = vec![];
+    for notation in &notations {
+        batches.push(match dice::parse_batch(notation, &macros) {
+            Ok(batch) => Ok(batch),
+            Err(DiceErr::ParseErr(e)) => {
+                let mut line = format!("`[[{}]]`: {}", notation.replace('`', "'"), e.kind);
+                if let Some(suggestion) = &e.suggestion {
+                    line.push_str(&format!(", *{suggestion}*"));
+                }
+                Err(line)
+            }
+            Err(e) => return Err(e.into()),
+        });
+    }
+
+    let source = source_kind(data, guild_id).await?;
+    let mut lines = vec![];
+    let mut rows = vec![];
+    // Shown while a roll waits for the beacon, until dropped
+    let mut typing = None;
+    for batch in &batches {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(line) => {
+                lines.push(line.clone());
+                continue;
+            }
+        };
+        let rolling = roll_batch(&data.random, source, batch);
+        tokio::pin!(rolling);
+        let rolled = match tokio::time::timeout(WAIT_NOTICE, rolling.as_mut()).await {
+            Ok(rolled) => rolled,
+            Err(_) => {
+                typing.get_or_insert_with(|| msg.channel_id.start_typing(&ctx.http));
+                rolling.await
+            }
+        };
+        match rolled? {
+            Ok(rolled) => {
+                lines.extend(
+                    rolled
+                        .iter()
+                        .map(|row| format_row(&row.title, row.notation, &row.rolled)),
+                );
+                rows.extend(rolled);
+            }
+            Err(reason) => lines.push(reason),
+        }
+    }
+
+    let mut embed = CreateEmbed::default().description(truncate(&lines.join("\n")));
+    if rows.is_empty() {
+        embed = embed.color(COLOUR_ERROR);
+    } else {
+        let ids = data
+            .roll_repo
+            .log_rolls(row_logs(
+                &rows,
+                guild_id,
+                msg.channel_id.get(),
+                user_id,
+                Visibility::Public,
+                None,
+            ))
+            .await?;
+        embed = embed
+            .color(roll_colour(&rows))
+            .footer(CreateEmbedFooter::new(format_roll_ids(&ids)));
+    }
+    msg.channel_id
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .embed(embed)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new().replied_user(false)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_inline;
+
+    #[test]
+    fn test_find_inline() {
+        assert_eq!(
+            find_inline("I swing [[1d20+5]] and hit for [[ 1d8+3 # Slashing ]]!"),
+            ["1d20+5", "1d8+3 # Slashing"]
+        );
+        assert!(find_inline("no rolls [here] or [[]] or [[1d6").is_empty());
+        assert_eq!(find_inline("[[1d4]]]]"), ["1d4"]);
+        assert_eq!(find_inline(&"[[1d6]]".repeat(10)).len(), 5);
+        assert_eq!(
+            find_inline("see [[Main Page]], [[File:map.png]] and [[2d6 +]] or [[$sword]]"),
+            ["2d6 +", "$sword"]
+        );
+    }
+}
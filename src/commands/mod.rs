@@ -1,27 +1,81 @@
 use std::sync::{atomic::AtomicU64, Arc};
 
+mod beacon;
+pub use beacon::beacon;
+mod history;
+pub use history::history;
+mod inline;
 mod music;
 pub use music::{pingmusic, stop};
+mod odds;
+pub use odds::odds;
 mod ping;
 pub use ping::ping;
+mod reroll;
 mod roll;
 pub use roll::roll;
+mod roll_macro;
+pub use roll_macro::roll_macro;
+mod source;
+pub use source::rollsource;
+mod verify;
+pub use verify::verify;
+mod visibility;
+pub use visibility::rollsettings;
 
-use crate::repo::{music::MusicRepo, nist_beacon::NistBeaconRepo};
+use poise::serenity_prelude as serenity;
+
+use crate::repo::{
+    music::MusicRepo, nist_beacon::NistBeaconRepo, random::RandomSources, roll::RollRepo,
+    roll_macro::MacroRepo,
+};
 
 pub struct Data {
     ping: AtomicU64,
     nist_repo: Arc<NistBeaconRepo>,
     music_repo: Arc<MusicRepo>,
+    roll_repo: Arc<RollRepo>,
+    macro_repo: Arc<MacroRepo>,
+    random: Arc<RandomSources>,
 }
 impl Data {
-    pub fn new(nist_repo: Arc<NistBeaconRepo>, music_repo: Arc<MusicRepo>) -> Self {
+    pub fn new(
+        nist_repo: Arc<NistBeaconRepo>,
+        music_repo: Arc<MusicRepo>,
+        roll_repo: Arc<RollRepo>,
+        macro_repo: Arc<MacroRepo>,
+        random: Arc<RandomSources>,
+    ) -> Self {
         Self {
             ping: AtomicU64::new(0),
             nist_repo,
             music_repo,
+            roll_repo,
+            macro_repo,
+            random,
+        }
+    }
+}
+
+/// Handle gateway events that don't belong to a single command invocation,
+/// such as buttons that must keep working after a restart and inline rolls
+/// in chat messages.
+pub async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    _framework: poise::FrameworkContext<'_, Data, Error>,
+    data: &Data,
+) -> Result<()> {
+    match event {
+        serenity::FullEvent::InteractionCreate {
+            interaction: serenity::Interaction::Component(interaction),
+        } => visibility::handle_reveal(ctx, data, interaction).await?,
+        serenity::FullEvent::Message { new_message } => {
+            inline::handle_message(ctx, data, new_message).await?
         }
+        _ => {}
     }
+    Ok(())
 }
 
 pub type Error = anyhow::Error;
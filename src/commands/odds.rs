@@ -0,0 +1,187 @@
+use poise::serenity_prelude::{Colour, CreateAttachment, CreateEmbed};
+use poise::CreateReply;
+
+use crate::dice::{self, CmpOp, Compare, DiceErr, Distribution, Notation};
+
+use super::{
+    roll::{embed_error, format_parse_err},
+    Context, Result,
+};
+
+const COLOUR_ODDS: Colour = Colour::from_rgb(0, 170, 255);
+const PERCENTILES: [u8; 7] = [5, 10, 25, 50, 75, 90, 95];
+const CHART_NAME: &str = "odds.png";
+/// Matches the bar colours of [`dice::histogram`]
+const LEGEND: [&str; 2] = ["🟦", "🟧"];
+
+/// Exact probabilities of a dice notation, with a chart
+#[poise::command(slash_command)]
+pub async fn odds(
+    ctx: Context<'_>,
+    #[description = "Dice notation"] notation: String,
+    #[description = "Chance to reach at least this, overrides a DC in the notation"] target: Option<
+        i64,
+    >,
+    #[description = "Another notation to draw on the same chart"] compare: Option<String>,
+) -> Result<()> {
+    let Some(parsed) = parse(ctx, &notation).await? else {
+        return Ok(());
+    };
+    let compared = match compare {
+        Some(compare) => match parse(ctx, &compare).await? {
+            Some(compared) => Some(compared),
+            None => return Ok(()),
+        },
+        None => None,
+    };
+
+    // Analysis can outlast the time Discord gives to answer
+    ctx.defer().await?;
+    let Some(dist) = analyze(ctx, &parsed).await? else {
+        return Ok(());
+    };
+    let compared = match compared {
+        Some(other) => match analyze(ctx, &other).await? {
+            Some(other_dist) => Some((other, other_dist)),
+            None => return Ok(()),
+        },
+        None => None,
+    };
+    let target = target.map(|value| Compare {
+        op: CmpOp::Ge,
+        value,
+    });
+
+    let embed = CreateEmbed::default()
+        .color(COLOUR_ODDS)
+        .image(format!("attachment://{CHART_NAME}"));
+    let (embed, dists) = match compared {
+        None => {
+            let title = match &parsed.label {
+                Some(label) => format!("Odds of {label}"),
+                None => format!("Odds of {parsed}"),
+            };
+            let text = format_odds(&dist, target.or(parsed.target));
+            (embed.title(title).description(text), vec![dist])
+        }
+        Some((other, other_dist)) => {
+            let embed = embed.title(format!("{parsed} vs {other}")).fields(
+                [(&parsed, &dist), (&other, &other_dist)]
+                    .into_iter()
+                    .zip(LEGEND)
+                    .map(|((notation, dist), square)| {
+                        (
+                            format!("{square} {notation}"),
+                            format_odds(dist, target.or(notation.target)),
+                            true,
+                        )
+                    }),
+            );
+            (embed, vec![dist, other_dist])
+        }
+    };
+    let chart =
+        tokio::task::spawn_blocking(move || dice::histogram(&dists.iter().collect::<Vec<_>>()))
+            .await?;
+
+    ctx.send(
+        CreateReply::default()
+            .embed(embed)
+            .attachment(CreateAttachment::bytes(chart, CHART_NAME))
+            .reply(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Parse a notation, telling the caller if that fails.
+async fn parse(ctx: Context<'_>, notation: &str) -> Result<Option<Notation>> {
+    match dice::parse(notation) {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(DiceErr::ParseErr(e)) => {
+            ctx.send(embed_error(
+                "Could not parse notation",
+                format_parse_err(notation, &e),
+            ))
+            .await?;
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Analyze a notation, telling the caller if that fails.
+async fn analyze(ctx: Context<'_>, parsed: &Notation) -> Result<Option<Distribution>> {
+    // Big convolutions take a while, keep them off the async workers
+    let analyzed = parsed.clone();
+    match tokio::task::spawn_blocking(move || analyzed.odds()).await? {
+        Ok(dist) => Ok(Some(dist)),
+        Err(e) => {
+            let reason = match e {
+                DiceErr::DivByZero => "it can divide by zero".into(),
+                DiceErr::Overflow => "its results can be too large".into(),
+                DiceErr::TooComplex => "it has too many outcomes to compute exactly".into(),
+                DiceErr::Unsupported(what) => format!("{what} is not supported"),
+                e => return Err(e.into()),
+            };
+            ctx.send(embed_error(
+                "Could not compute odds",
+                format!("`{parsed}`: {reason}"),
+            ))
+            .await?;
+            Ok(None)
+        }
+    }
+}
+
+fn format_odds(dist: &Distribution, target: Option<Compare>) -> String {
+    let mut text = format!(
+        "Mean: **{:.2}**\nStd-dev: {:.2}\nRange: {} to {}",
+        dist.mean(),
+        dist.std_dev(),
+        dist.min(),
+        dist.max()
+    );
+    if let Some(target) = target {
+        text.push_str(&format!(
+            "\nP({} {}): **{}**",
+            target.op,
+            target.value,
+            format_percent(dist.prob(|x| target.matches(x)))
+        ));
+    }
+
+    text.push_str("\n```\n");
+    for q in PERCENTILES {
+        text.push_str(&format!(
+            "{q:>3}% ≤ {}\n",
+            dist.percentile(f64::from(q) / 100.0)
+        ));
+    }
+    text.push_str("```");
+    text
+}
+
+/// A probability as a percentage, keeping long odds readable.
+fn format_percent(p: f64) -> String {
+    let percent = p * 100.0;
+    if percent > 0.0 && percent < 0.01 {
+        format!("{percent:.2e}%")
+    } else {
+        format!("{percent:.2}%")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_percent;
+
+    #[test]
+    fn test_format_percent() {
+        assert_eq!(format_percent(0.5), "50.00%");
+        assert_eq!(format_percent(1.0 / 20.0), "5.00%");
+        assert_eq!(format_percent(0.0), "0.00%");
+        assert_eq!(format_percent(1.0 / 6f64.powi(10)), "1.65e-6%");
+    }
+}
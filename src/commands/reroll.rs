@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage,
+};
+
+use crate::dice::Repeated;
+
+use super::{
+    roll::{
+        embed_roll, format_breakdown, format_roll_ids, format_rows, format_waiting, roll_batch,
+        roll_colour, row_logs, source_kind, truncate, COLOUR_ERROR, WAIT_NOTICE,
+    },
+    visibility::Visibility,
+    Context, Result,
+};
+
+/// How long the buttons under a roll keep working
+const REROLL_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+const AGAIN: &str = "again";
+const ADVANTAGE: &str = "adv";
+const BREAKDOWN: &str = "breakdown";
+
+/// Buttons under the `n`th message of a roll, the first being the reply
+/// to the command itself.
+pub(super) fn buttons(ctx_id: u64, n: usize, batch: &[Repeated]) -> CreateActionRow {
+    let mut buttons = vec![CreateButton::new(format!("{ctx_id}:{n}:{AGAIN}"))
+        .label("Roll again")
+        .emoji('🎲')
+        .style(ButtonStyle::Primary)];
+    if with_advantage(batch).is_some() {
+        buttons.push(
+            CreateButton::new(format!("{ctx_id}:{n}:{ADVANTAGE}"))
+                .label("Reroll with advantage")
+                .style(ButtonStyle::Secondary),
+        );
+    }
+    buttons.push(
+        CreateButton::new(format!("{ctx_id}:{n}:{BREAKDOWN}"))
+            .label("Show breakdown")
+            .style(ButtonStyle::Secondary),
+    );
+    CreateActionRow::Buttons(buttons)
+}
+
+/// The batch with advantage on its d20s, if any row has one to give it to.
+fn with_advantage(batch: &[Repeated]) -> Option<Vec<Repeated>> {
+    let mut changed = false;
+    let batch = batch
+        .iter()
+        .map(|r| Repeated {
+            notation: match r.notation.with_advantage() {
+                Some(n) => {
+                    changed = true;
+                    n
+                }
+                None => r.notation.clone(),
+            },
+            times: r.times,
+        })
+        .collect();
+    changed.then_some(batch)
+}
+
+/// Answer the buttons under a public roll until they time out.
+///
+/// `breakdown` is that of the first roll. Only whoever rolled may press them.
+pub(super) async fn collect(
+    ctx: Context<'_>,
+    batch: Vec<Repeated>,
+    breakdown: String,
+) -> Result<()> {
+    let ctx_id = ctx.id();
+    let prefix = format!("{ctx_id}:");
+    let mut breakdowns = vec![breakdown];
+
+    while let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .filter({
+            let prefix = prefix.clone();
+            move |press| press.data.custom_id.starts_with(&prefix)
+        })
+        .timeout(REROLL_TIMEOUT)
+        .await
+    {
+        let mut parts = press.data.custom_id.splitn(3, ':').skip(1);
+        let (Some(Ok(n)), Some(action)) = (parts.next().map(str::parse::<usize>), parts.next())
+        else {
+            acknowledge(ctx, &press).await?;
+            continue;
+        };
+
+        if press.user.id != ctx.author().id {
+            let text = format!("Only <@{}> can use these buttons.", ctx.author().id);
+            respond_ephemeral(ctx, &press, CreateEmbed::default().description(text)).await?;
+            continue;
+        }
+
+        let rerolled = match action {
+            AGAIN => batch.clone(),
+            ADVANTAGE => match with_advantage(&batch) {
+                Some(batch) => batch,
+                None => {
+                    let text = "This roll has no d20 to give advantage to.";
+                    respond_ephemeral(ctx, &press, CreateEmbed::default().description(text))
+                        .await?;
+                    continue;
+                }
+            },
+            BREAKDOWN => {
+                let Some(breakdown) = breakdowns.get(n) else {
+                    acknowledge(ctx, &press).await?;
+                    continue;
+                };
+                let embed = CreateEmbed::default()
+                    .title("Breakdown")
+                    .description(truncate(breakdown));
+                respond_ephemeral(ctx, &press, embed).await?;
+                continue;
+            }
+            _ => {
+                acknowledge(ctx, &press).await?;
+                continue;
+            }
+        };
+
+        let source = source_kind(ctx.data(), ctx.guild_id().map(|id| id.get())).await?;
+        let rolling = roll_batch(&ctx.data().random, source, &rerolled);
+        tokio::pin!(rolling);
+        // The press must be answered within seconds, so a roll waiting for
+        // the beacon answers with a notice and sends its result after it
+        let (rows, answered) = match tokio::time::timeout(WAIT_NOTICE, rolling.as_mut()).await {
+            Ok(rows) => (rows?, false),
+            Err(_) => {
+                let notice = CreateEmbed::default()
+                    .description(format_waiting(&ctx.data().nist_repo.status()));
+                respond_ephemeral(ctx, &press, notice).await?;
+                (rolling.await?, true)
+            }
+        };
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(reason) => {
+                let embed = CreateEmbed::default()
+                    .color(COLOUR_ERROR)
+                    .title("Could not evaluate notation")
+                    .description(reason);
+                if answered {
+                    press
+                        .create_followup(
+                            ctx.serenity_context(),
+                            CreateInteractionResponseFollowup::new()
+                                .embed(embed)
+                                .ephemeral(true),
+                        )
+                        .await?;
+                } else {
+                    respond_ephemeral(ctx, &press, embed).await?;
+                }
+                continue;
+            }
+        };
+        let ids = ctx
+            .data()
+            .roll_repo
+            .log_rolls(row_logs(
+                &rows,
+                ctx.guild_id().map(|id| id.get()),
+                ctx.channel_id().get(),
+                ctx.author().id.get(),
+                Visibility::Public,
+                None,
+            ))
+            .await?;
+        breakdowns.push(format_breakdown(&rows));
+
+        let (title, text) = format_rows(&rows);
+        let embed = embed_roll(title, text, roll_colour(&rows))
+            .footer(CreateEmbedFooter::new(format_roll_ids(&ids)));
+        let buttons = buttons(ctx_id, breakdowns.len() - 1, &batch);
+        if answered {
+            press
+                .create_followup(
+                    ctx.serenity_context(),
+                    CreateInteractionResponseFollowup::new()
+                        .embed(embed)
+                        .components(vec![buttons]),
+                )
+                .await?;
+        } else {
+            press
+                .create_response(
+                    ctx.serenity_context(),
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .embed(embed)
+                            .components(vec![buttons]),
+                    ),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer a press that has nothing to show, so it does not fail.
+async fn acknowledge(ctx: Context<'_>, press: &ComponentInteraction) -> Result<()> {
+    press
+        .create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::Acknowledge,
+        )
+        .await?;
+    Ok(())
+}
+
+async fn respond_ephemeral(
+    ctx: Context<'_>,
+    press: &ComponentInteraction,
+    embed: CreateEmbed,
+) -> Result<()> {
+    press
+        .create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(embed)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
@@ -1,145 +1,648 @@
+use std::time::Duration;
+
 use poise::{
-    serenity_prelude::{Colour, CreateEmbed},
-    CreateReply,
+    serenity_prelude::{Colour, CreateEmbed, CreateEmbedFooter},
+    ChoiceParameter, CreateReply,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    dice::{
+        self, Compare, DiceErr, DiceRoll, ExplodeKind, Expr, Faces, Macros, Notation, ParseErr,
+        Repeated, Rolled,
+    },
+    repo::{
+        nist_beacon::{BeaconStatus, BitSpan},
+        random::{RandomSources, SourceKind},
+        roll::NewRollLog,
+    },
 };
 
-use super::{Context, Result};
+use super::{
+    reroll,
+    roll_macro::{autocomplete_notation, user_macros},
+    visibility::{self, Visibility},
+    Context, Data, Result,
+};
+
+const COLOUR_ROLL: Colour = Colour::from_rgb(170, 255, 0);
+const COLOUR_BOTCH: Colour = Colour::from_rgb(255, 60, 0);
+const COLOUR_FAIL: Colour = Colour::from_rgb(255, 170, 0);
+pub(super) const COLOUR_ERROR: Colour = Colour::from_rgb(255, 0, 0);
+/// How long a roll may take before the caller is told it waits on the beacon
+pub(super) const WAIT_NOTICE: Duration = Duration::from_secs(2);
+/// Embed descriptions are cut off past this many chars, leaving room under
+/// Discord's limit of 4096 for the marker
+const MAX_DESCRIPTION_LEN: usize = 4000;
+
+pub(super) fn embed_roll(title: &str, text: String, colour: Colour) -> CreateEmbed {
+    CreateEmbed::default()
+        .color(colour)
+        .title(title)
+        .description(text)
+}
 
-fn embed_result(text: String) -> CreateReply {
+/// An error only the caller can see, so typos don't clutter the channel.
+pub(super) fn embed_error(title: &str, text: String) -> CreateReply {
     CreateReply::default()
         .embed(
             CreateEmbed::default()
-                .color(Colour::from_rgb(170, 255, 0))
-                .title("Roll")
+                .color(COLOUR_ERROR)
+                .title(title)
                 .description(text),
         )
-        .reply(true)
+        .ephemeral(true)
 }
 
 #[poise::command(slash_command)]
 pub async fn roll(
     ctx: Context<'_>,
-    #[description = "Dice notation"] notation: String,
+    #[description = "Dice notation, `$name` rolls a saved macro"]
+    #[autocomplete = "autocomplete_notation"]
+    notation: String,
+    #[description = "Who sees the result, defaults to the channel's setting"] visibility: Option<
+        Visibility,
+    >,
+) -> Result<()> {
+    roll_notation(ctx, &notation, visibility).await
+}
+
+/// Roll a notation, which may reference the caller's macros.
+pub(super) async fn roll_notation(
+    ctx: Context<'_>,
+    notation: &str,
+    visibility: Option<Visibility>,
 ) -> Result<()> {
-    let repo = ctx.data().nist_repo.clone();
-
-    let mut ns = &notation[..];
-    let mut raw_notations = vec![];
-    let mut start_from_1 = false;
-
-    while !ns.is_empty() {
-        let fns = if start_from_1 { &ns[1..] } else { ns };
-        match fns.find(&['+', '-']) {
-            Some(i) => {
-                let i = if start_from_1 { i + 1 } else { i };
-                raw_notations.push(&ns[..i]);
-                ns = &ns[i..];
-                start_from_1 = true;
-            }
-            None => {
-                raw_notations.push(ns);
-                break;
-            }
+    let macros = if notation.contains('$') {
+        user_macros(
+            &ctx.data().macro_repo,
+            ctx.author().id.get(),
+            ctx.guild_id().map(|id| id.get()),
+        )
+        .await?
+    } else {
+        Macros::new()
+    };
+    let batch = match dice::parse_batch(notation, &macros) {
+        Ok(batch) => batch,
+        Err(DiceErr::ParseErr(e)) => {
+            ctx.send(embed_error(
+                "Could not parse notation",
+                format_parse_err(notation, &e),
+            ))
+            .await?;
+            return Ok(());
         }
+        Err(e) => return Err(e.into()),
+    };
+
+    let setting = ctx
+        .data()
+        .roll_repo
+        .channel_setting(ctx.channel_id().get())
+        .await?;
+    let visibility = visibility
+        .or_else(|| Visibility::from_key(setting.as_ref()?.roll_visibility.as_deref()?))
+        .unwrap_or_default();
+    let gm_id = setting.and_then(|s| s.gm_id).map(|id| id as u64);
+    if gm_id.is_none() && matches!(visibility, Visibility::Gm | Visibility::Blind) {
+        ctx.send(embed_error(
+            "No GM in this channel",
+            "Set one with `/rollsettings gm:` to roll GM-only or blind.".into(),
+        ))
+        .await?;
+        return Ok(());
     }
 
-    let mut dice: Vec<Dice> = vec![];
-    let mut modifiers: Vec<i64> = vec![];
-    for n in raw_notations {
-        let n: String = n.chars().filter(|c| !c.is_whitespace()).collect();
-        if let Ok(i) = n.parse::<i64>() {
-            modifiers.push(i);
-        } else if let Some(d) = parse_dice(&n) {
-            dice.push(d);
-        } else {
-            ctx.reply(&format!("Could not parse notation: {}", &notation))
+    let source = source_kind(ctx.data(), ctx.guild_id().map(|id| id.get())).await?;
+    let rows = match roll_batch_waiting(ctx, source, &batch).await? {
+        Ok(rows) => rows,
+        Err(reason) => {
+            ctx.send(embed_error("Could not evaluate notation", reason))
                 .await?;
             return Ok(());
         }
+    };
+    let colour = roll_colour(&rows);
+    let (title, text) = format_rows(&rows);
+    let ids = ctx
+        .data()
+        .roll_repo
+        .log_rolls(row_logs(
+            &rows,
+            ctx.guild_id().map(|id| id.get()),
+            ctx.channel_id().get(),
+            ctx.author().id.get(),
+            visibility,
+            gm_id,
+        ))
+        .await?;
+    let footer = format_roll_ids(&ids);
+
+    if visibility == Visibility::Public {
+        let breakdown = format_breakdown(&rows);
+        let embed = embed_roll(title, text, colour).footer(CreateEmbedFooter::new(footer));
+        let buttons = reroll::buttons(ctx.id(), 0, &batch);
+        ctx.send(
+            CreateReply::default()
+                .embed(embed)
+                .components(vec![buttons])
+                .reply(true),
+        )
+        .await?;
+        reroll::collect(ctx, batch, breakdown).await?;
+    } else {
+        visibility::send_hidden(ctx, visibility, gm_id, title, text, colour, footer).await?;
     }
 
-    let mut total_value = 0;
-    let mut results = String::new();
+    Ok(())
+}
 
-    for d in dice {
-        let dstr = d.to_string();
-        results.push_str("`[");
-        results.push_str(&dstr);
-        results.push_str("]`: ");
+/// The title and text of the embed of a roll.
+pub(super) fn format_rows<'a>(rows: &'a [Row]) -> (&'a str, String) {
+    match rows {
+        [row] => (
+            row.notation.label.as_deref().unwrap_or("Roll"),
+            truncate(&format_rolled(row.notation, &row.rolled)),
+        ),
+        rows => (
+            "Roll",
+            truncate(
+                &rows
+                    .iter()
+                    .map(|row| format_row(&row.title, row.notation, &row.rolled))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        ),
+    }
+}
 
-        let mut rolled = Vec::with_capacity(d.count);
-        for _ in 0..d.count {
-            let x = repo.rand(1, d.face).await?;
-            if x == 1 || x == d.face {
-                rolled.push(format!("**({x})**"));
-            } else {
-                rolled.push(x.to_string());
-            }
-            total_value += x;
+/// Cut `text` to fit in an embed, saying how much was left out.
+pub(super) fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_DESCRIPTION_LEN) {
+        Some((i, _)) => {
+            let elided = text[i..].chars().count();
+            format!("{}…\n-# {elided} more character(s) not shown", &text[..i])
         }
+        None => text.into(),
+    }
+}
 
-        results.push_str(&rolled.join(", "));
-        results.push('\n');
+/// Every row in full, along with the beacon bits it drew.
+pub(super) fn format_breakdown(rows: &[Row]) -> String {
+    rows.iter()
+        .map(|row| {
+            let source = match &row.spans {
+                Some(spans) => {
+                    let bits: usize = spans.iter().map(|s| usize::from(s.len)).sum();
+                    let mut pulses: Vec<_> = spans
+                        .iter()
+                        .map(|s| {
+                            format!("{} {}/{}", s.provider.name(), s.chain_index, s.pulse_index)
+                        })
+                        .collect();
+                    pulses.dedup();
+                    let kind = if row.source == SourceKind::Stretched {
+                        "stretched"
+                    } else {
+                        "beacon"
+                    };
+                    format!("{bits} {kind} bit(s) from {}", pulses.join(", "))
+                }
+                None => format!("Rolled with {}", row.source.name()),
+            };
+            format!(
+                "**{}**\n{}\n-# {source}",
+                row.title,
+                format_rolled(row.notation, &row.rolled),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// The random source rolls in a guild draw from.
+pub(super) async fn source_kind(data: &Data, guild_id: Option<u64>) -> Result<SourceKind> {
+    let setting = match guild_id {
+        Some(id) => data.roll_repo.guild_setting(id).await?,
+        None => None,
+    };
+    Ok(setting
+        .and_then(|s| SourceKind::from_key(s.random_source.as_deref()?))
+        .filter(|kind| kind.guild_selectable())
+        .unwrap_or(data.random.default))
+}
+
+/// One row of a batch once rolled.
+pub(super) struct Row<'a> {
+    pub title: String,
+    pub notation: &'a Notation,
+    pub rolled: Rolled,
+    pub source: SourceKind,
+    /// The beacon bits the row drew, if it drew from the beacon
+    pub spans: Option<Vec<BitSpan>>,
+}
+
+/// Tell the caller why a roll is taking a while.
+pub(super) fn format_waiting(status: &BeaconStatus) -> String {
+    let next = match status.next_pulse {
+        Some(t) if t > OffsetDateTime::now_utc() => {
+            format!("the next pulse is due <t:{}:R>", t.unix_timestamp())
+        }
+        _ => "waiting for the next pulse".into(),
+    };
+    format!(
+        "⏳ Out of beacon bits, {next}. Rolls in line: {}.",
+        status.waiting
+    )
+}
+
+/// [`roll_batch`], telling the caller when it has to wait for the beacon.
+async fn roll_batch_waiting<'a>(
+    ctx: Context<'_>,
+    source: SourceKind,
+    batch: &'a [Repeated],
+) -> Result<std::result::Result<Vec<Row<'a>>, String>> {
+    let rolling = roll_batch(&ctx.data().random, source, batch);
+    tokio::pin!(rolling);
+    if let Ok(rows) = tokio::time::timeout(WAIT_NOTICE, rolling.as_mut()).await {
+        return rows;
     }
 
-    let modded = modifiers
-        .into_iter()
-        .enumerate()
-        .fold(String::new(), |acc, (i, x)| {
-            total_value += x;
-
-            if i == 0 {
-                return x.to_string();
-            }
-            if x < 0 {
-                format!("{} - {}", acc, -x)
+    let notice = ctx
+        .send(
+            CreateReply::default()
+                .content(format_waiting(&ctx.data().nist_repo.status()))
+                .ephemeral(true),
+        )
+        .await?;
+    let rows = rolling.await;
+    notice.delete(ctx).await?;
+    rows
+}
+
+/// Roll every repetition of every row of a batch, each with its own draw.
+///
+/// The inner error is why a row could not be evaluated, to show the caller.
+pub(super) async fn roll_batch<'a>(
+    random: &RandomSources,
+    source: SourceKind,
+    batch: &'a [Repeated],
+) -> Result<std::result::Result<Vec<Row<'a>>, String>> {
+    let mut rows = vec![];
+    for r in batch {
+        for i in 0..r.times {
+            let mut draw = random.draw(source);
+            let rolled = match r.notation.roll(&mut draw).await {
+                Ok(rolled) => rolled,
+                Err(e @ (DiceErr::DivByZero | DiceErr::Overflow)) => {
+                    let reason = match e {
+                        DiceErr::DivByZero => "division by zero",
+                        _ => "the result is too large",
+                    };
+                    return Ok(Err(format!("`{}`: {reason}", r.notation)));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let title = match &r.notation.label {
+                Some(label) => label.clone(),
+                None => r.notation.to_string(),
+            };
+            let title = if r.times > 1 {
+                format!("{title} #{}", i + 1)
             } else {
-                format!("{} + {}", acc, x)
-            }
-        });
+                title
+            };
+            rows.push(Row {
+                title,
+                notation: &r.notation,
+                rolled,
+                source,
+                spans: draw.into_spans(),
+            });
+        }
+    }
+    Ok(Ok(rows))
+}
+
+/// Botches stand out the most, then missed targets.
+pub(super) fn roll_colour(rows: &[Row]) -> Colour {
+    let botch = rows
+        .iter()
+        .any(|row| row.rolled.dice.iter().any(DiceRoll::is_botch));
+    let failed = rows.iter().any(|row| {
+        row.notation
+            .target
+            .is_some_and(|t| !t.matches(row.rolled.total))
+    });
+    match (botch, failed) {
+        (true, _) => COLOUR_BOTCH,
+        (false, true) => COLOUR_FAIL,
+        (false, false) => COLOUR_ROLL,
+    }
+}
+
+/// The rows of a roll as they go into the roll log.
+pub(super) fn row_logs(
+    rows: &[Row],
+    guild_id: Option<u64>,
+    channel_id: u64,
+    user_id: u64,
+    visibility: Visibility,
+    gm_id: Option<u64>,
+) -> Vec<NewRollLog> {
+    rows.iter()
+        .map(|row| NewRollLog {
+            guild_id,
+            channel_id,
+            user_id,
+            visibility: visibility.key().into(),
+            notation: row.notation.to_string(),
+            label: row.notation.label.clone(),
+            dice: log_dice(&row.rolled),
+            total: row.rolled.total,
+            draws: row
+                .spans
+                .as_ref()
+                .and_then(|spans| serde_json::to_string(spans).ok()),
+            source: row.source.key().into(),
+            gm_id: matches!(visibility, Visibility::Gm | Visibility::Blind)
+                .then_some(gm_id)
+                .flatten(),
+        })
+        .collect()
+}
+
+/// Roll ids to check with `/verify`, as a range for a batch.
+pub(super) fn format_roll_ids(ids: &[i32]) -> String {
+    match ids {
+        [id] => format!("Roll ID {id}"),
+        [first, .., last] => format!("Roll IDs {first}–{last}"),
+        [] => String::new(),
+    }
+}
+
+/// The dice of a roll as JSON, for the roll log.
+pub(super) fn log_dice(rolled: &Rolled) -> String {
+    let groups: Vec<_> = rolled
+        .dice
+        .iter()
+        .map(|d| {
+            let face = |x: i64| match &d.dice.faces {
+                Faces::Named(labels) => serde_json::json!(labels[x as usize]),
+                _ => serde_json::json!(x),
+            };
+            let (dropped, kept): (Vec<_>, Vec<_>) =
+                d.dice_rolled.iter().partition(|die| die.dropped);
+            serde_json::json!({
+                "dice": d.dice.to_string(),
+                "kept": kept.iter().map(|die| face(die.value)).collect::<Vec<_>>(),
+                "dropped": dropped.iter().map(|die| face(die.value)).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    serde_json::Value::from(groups).to_string()
+}
+
+/// The notation with a caret under the error, the reason and any hint.
+pub(super) fn format_parse_err(notation: &str, e: &ParseErr) -> String {
+    // Keep the notation on one line so the caret stays aligned
+    let notation: String = notation
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect();
+    let mut text = format!(
+        "```\n{}\n{}^\n```\n{}",
+        notation.replace('`', "'"),
+        " ".repeat(e.pos),
+        e.kind
+    );
+    if let Some(suggestion) = &e.suggestion {
+        text.push_str(&format!("\n*Hint: {suggestion}*"));
+    }
+    text
+}
 
-    if !modded.is_empty() {
-        results.push_str("\nmodifier: ");
-        results.push_str(&modded);
+pub(super) fn format_rolled(notation: &Notation, rolled: &Rolled) -> String {
+    let expr = &notation.expr;
+    let mut results = String::new();
+
+    for d in &rolled.dice {
+        results.push_str("`[");
+        results.push_str(&d.dice.to_string());
+        results.push_str("]`: ");
+        results.push_str(&format_dice_line(d));
         results.push('\n');
     }
 
-    results.push_str(&format!("\nResults: **{total_value}**"));
+    if !expr.is_single_dice() {
+        results.push_str("\nexpression: ");
+        results.push_str(&format_expanded(expr, rolled));
+        results.push('\n');
+    }
 
-    ctx.send(embed_result(results)).await?;
+    let label = match expr {
+        Expr::Dice(i) if rolled.dice[*i].dice.success.is_some() => "Net successes",
+        _ => "Results",
+    };
+    results.push_str(&format!("\n{label}: **{}**", rolled.total));
+    if let Some(target) = notation.target {
+        results.push_str(&format!(
+            "\n{} DC {}: {}",
+            target.op,
+            target.value,
+            format_verdict(target, rolled.total)
+        ));
+    }
+    results
+}
 
-    Ok(())
+/// A compact single line for one row of a batch.
+pub(super) fn format_row(title: &str, notation: &Notation, rolled: &Rolled) -> String {
+    let expr = &notation.expr;
+    let dice: Vec<_> = rolled
+        .dice
+        .iter()
+        .map(|d| format!("`[{}]` {}", d.dice, format_dice_line(d)))
+        .collect();
+
+    let mut row = format!("**{title}**: {}", dice.join(" | "));
+    if !expr.is_single_dice() {
+        row.push_str(" ⇒ ");
+        row.push_str(&format_expanded(expr, rolled));
+    }
+    row.push_str(&format!(" = **{}**", rolled.total));
+    if let Some(target) = notation.target {
+        row.push_str(&format!(
+            " {} DC {} — {}",
+            target.op,
+            target.value,
+            format_verdict(target, rolled.total)
+        ));
+    }
+    row
+}
+
+fn format_verdict(target: Compare, total: i64) -> String {
+    let verdict = if target.matches(total) {
+        "Success"
+    } else {
+        "Failure"
+    };
+    format!("**{verdict}** (margin {:+})", target.margin(total))
 }
 
-struct Dice {
-    face: i64,
-    count: usize,
+/// The expression with each dice group replaced by its total.
+fn format_expanded(expr: &Expr, rolled: &Rolled) -> String {
+    expr.render(&|i| {
+        rolled.dice[i]
+            .total()
+            .map_or_else(|_| "?".into(), |x| x.to_string())
+    })
 }
 
-impl std::fmt::Display for Dice {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}d{}", self.count, self.face)
+/// The dice of a group followed by its tally or successes, if any.
+fn format_dice_line(d: &DiceRoll) -> String {
+    let mut line = format_dice_roll(d);
+    if let Faces::Named(labels) = &d.dice.faces {
+        line.push_str(" → ");
+        line.push_str(&format_tally(labels, d));
     }
+    if let Some((successes, failures)) = d.successes() {
+        line.push_str(&format!(
+            " → {successes} success(es), {failures} failure(s)"
+        ));
+        if d.is_botch() {
+            line.push_str(" **Botch!**");
+        }
+    }
+    line
 }
 
-fn parse_dice(d: &str) -> Option<Dice> {
-    let d = d.trim().to_ascii_lowercase();
-    let mut face = 0;
-    let mut count = 0;
-    for (i, s) in d.split('d').enumerate() {
-        match i {
-            0 => {
-                count = s.parse().ok()?;
-            }
-            1 => {
-                face = s.parse().ok()?;
-            }
-            _ => return None,
+fn format_dice_roll(d: &DiceRoll) -> String {
+    let mut res = String::new();
+    for (i, die) in d.dice_rolled.iter().enumerate() {
+        if die.exploded {
+            res.push('→');
+        } else if i > 0 {
+            res.push_str(", ");
+        }
+
+        let faces = &d.dice.faces;
+        let penetrated =
+            die.exploded && d.dice.explode.map(|e| e.kind) == Some(ExplodeKind::Penetrate);
+        let mut rolls: String = die
+            .rerolled
+            .iter()
+            .map(|&x| format!("{}↻", format_face(faces, x)))
+            .collect();
+        let chain: Vec<_> = die
+            .rolls
+            .iter()
+            .map(|&x| {
+                // A penetrating die counts one less than it shows
+                let face = match faces {
+                    _ if !penetrated => format_face(faces, x),
+                    Faces::Range(_) | Faces::Custom(_) => (x - 1).to_string(),
+                    Faces::Fate | Faces::Named(_) => format!("{}−1", format_face(faces, x)),
+                };
+                if die.dropped {
+                    face
+                } else if let Some(s) = d.dice.success {
+                    // Successes in bold and failures underlined, judged on
+                    // the value the die is worth rather than each raw roll
+                    if s.on.matches(die.value) {
+                        format!("**{face}**")
+                    } else if s.fail.is_some_and(|f| f.matches(die.value)) {
+                        format!("__{face}__")
+                    } else {
+                        face
+                    }
+                } else if is_extreme(faces, x) {
+                    format!("**({face})**")
+                } else {
+                    face
+                }
+            })
+            .collect();
+        rolls.push_str(&chain.join("→"));
+
+        if die.dropped {
+            res.push_str(&format!("~~{rolls}~~"));
+        } else {
+            res.push_str(&rolls);
         }
     }
-    if face <= 0 || count == 0 {
-        return None;
+    res
+}
+
+fn format_face(faces: &Faces, x: i64) -> String {
+    match faces {
+        Faces::Fate => match x {
+            -1 => "−".into(),
+            0 => "␣".into(),
+            _ => "+".into(),
+        },
+        Faces::Named(labels) => labels[x as usize].clone(),
+        Faces::Range(_) | Faces::Custom(_) => x.to_string(),
     }
+}
 
-    Some(Dice { face, count })
+/// Whether `x` is the lowest or highest face of a numbered die.
+fn is_extreme(faces: &Faces, x: i64) -> bool {
+    match faces {
+        Faces::Range(_) | Faces::Custom(_) => x == faces.lowest() || x == faces.highest(),
+        Faces::Fate | Faces::Named(_) => false,
+    }
+}
+
+/// Count of each label rolled, in the order the labels were declared.
+fn format_tally(labels: &[String], d: &DiceRoll) -> String {
+    labels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, label)| {
+            let n = d.kept().filter(|die| die.value == i as i64).count();
+            (n > 0).then(|| format!("{label} ×{n}"))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dice::parse,
+        repo::random::{RandomSource, SeededRandom},
+    };
+
+    use super::{format_dice_roll, truncate, MAX_DESCRIPTION_LEN};
+
+    #[tokio::test]
+    async fn test_format_penetrate() {
+        let dice = &parse("1d6!p").unwrap().dice[0];
+        let mut roll = dice.roll(&mut SeededRandom::new(1).draw()).await.unwrap();
+        let first = roll.dice_rolled[0].clone();
+        roll.dice_rolled = [(6, false), (6, true), (3, true)]
+            .map(|(x, exploded)| {
+                let mut die = first.clone();
+                die.rolls = vec![x];
+                die.exploded = exploded;
+                die.value = if exploded { x - 1 } else { x };
+                die
+            })
+            .into();
+        assert_eq!(roll.total().unwrap(), 13);
+        assert_eq!(format_dice_roll(&roll), "**(6)**→**(5)**→2");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("1d20 = **12**"), "1d20 = **12**");
+        let text = "⚀".repeat(MAX_DESCRIPTION_LEN + 25);
+        let cut = truncate(&text);
+        assert!(cut.ends_with("…\n-# 25 more character(s) not shown"));
+        assert!(cut.chars().count() < 4096);
+    }
 }
@@ -0,0 +1,279 @@
+use entity::roll_macro;
+use poise::{serenity_prelude::CreateEmbed, CreateReply};
+
+use crate::{
+    dice::{self, DiceErr, Macros, ParseErrKind, MAX_MACRO_NAME},
+    repo::roll_macro::MacroRepo,
+};
+
+use super::{
+    roll::{embed_error, format_parse_err, roll_notation},
+    visibility::Visibility,
+    Context, Result,
+};
+
+/// Most macros a user may keep in one scope
+const MAX_MACROS: usize = 100;
+/// Most suggestions Discord takes for autocomplete
+const MAX_CHOICES: usize = 25;
+/// Longest suggestion Discord takes for autocomplete
+const MAX_CHOICE_LEN: usize = 100;
+
+/// Save notations to roll again by name
+#[poise::command(
+    slash_command,
+    rename = "macro",
+    subcommands("save", "run", "list", "delete"),
+    subcommand_required
+)]
+pub async fn roll_macro(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Save a notation to roll with `/macro run` or as `$name` in `/roll`
+#[poise::command(slash_command)]
+async fn save(
+    ctx: Context<'_>,
+    #[description = "Letters, digits and _"] name: String,
+    #[description = "Dice notation, may end with a # label"] notation: String,
+    #[description = "Only use it in this server"] server: Option<bool>,
+) -> Result<()> {
+    let name = name.trim_start_matches('$').to_ascii_lowercase();
+    if !dice::is_macro_name(&name) {
+        ctx.send(embed_error(
+            "Invalid macro name",
+            format!("A name is 1 to {MAX_MACRO_NAME} letters, digits or `_`."),
+        ))
+        .await?;
+        return Ok(());
+    }
+    let notation = notation.trim();
+    match dice::parse_labelled(notation) {
+        Ok(_) => {}
+        Err(DiceErr::ParseErr(e)) => {
+            let mut text = format_parse_err(notation, &e);
+            if matches!(e.kind, ParseErrKind::UnknownMacro(_)) {
+                text.push_str("\nMacros cannot use other macros.");
+            }
+            ctx.send(embed_error("Could not parse notation", text))
+                .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let user_id = ctx.author().id.get();
+    let guild_id = ctx
+        .guild_id()
+        .filter(|_| server == Some(true))
+        .map(|id| id.get());
+    let repo = &ctx.data().macro_repo;
+    let in_scope: Vec<_> = repo
+        .macros(user_id, guild_id)
+        .await?
+        .into_iter()
+        .filter(|m| m.guild_id == guild_id.map(|id| id as i64))
+        .collect();
+    if in_scope.len() >= MAX_MACROS && !in_scope.iter().any(|m| m.name == name) {
+        ctx.send(embed_error(
+            "Too many macros",
+            format!("You can keep at most {MAX_MACROS} macros here, delete some first."),
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let replaced = repo
+        .save_macro(user_id, guild_id, name.clone(), notation.into())
+        .await?;
+    let verb = if replaced { "Updated" } else { "Saved" };
+    let scope = if guild_id.is_some() {
+        " for this server"
+    } else {
+        ""
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(format!("{verb} `${name}`{scope}: `{notation}`"))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Roll a saved macro
+#[poise::command(slash_command)]
+async fn run(
+    ctx: Context<'_>,
+    #[description = "Name of the macro"]
+    #[autocomplete = "autocomplete_name"]
+    name: String,
+    #[description = "Who sees the result, defaults to the channel's setting"] visibility: Option<
+        Visibility,
+    >,
+) -> Result<()> {
+    let name = name.trim_start_matches('$');
+    if !dice::is_macro_name(name) {
+        ctx.send(embed_error(
+            "Invalid macro name",
+            format!("`{name}` cannot be the name of a macro."),
+        ))
+        .await?;
+        return Ok(());
+    }
+    roll_notation(ctx, &format!("${name}"), visibility).await
+}
+
+/// List your saved macros
+#[poise::command(slash_command)]
+async fn list(ctx: Context<'_>) -> Result<()> {
+    let macros = ctx
+        .data()
+        .macro_repo
+        .macros(ctx.author().id.get(), ctx.guild_id().map(|id| id.get()))
+        .await?;
+    let text = if macros.is_empty() {
+        "No macros yet, save one with `/macro save`.".into()
+    } else {
+        macros
+            .iter()
+            .map(|m| {
+                let scope = if m.guild_id.is_some() {
+                    " · this server"
+                } else {
+                    ""
+                };
+                format!("`${}` `{}`{scope}", m.name, m.notation)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                CreateEmbed::default()
+                    .title("Your macros")
+                    .description(text),
+            )
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a saved macro, the one `$name` rolls here
+#[poise::command(slash_command)]
+async fn delete(
+    ctx: Context<'_>,
+    #[description = "Name of the macro"]
+    #[autocomplete = "autocomplete_name"]
+    name: String,
+) -> Result<()> {
+    let name = name.trim_start_matches('$').to_ascii_lowercase();
+    let user_id = ctx.author().id.get();
+    let repo = &ctx.data().macro_repo;
+
+    // A server macro hides the one saved for everywhere, so it goes first
+    let mut deleted = false;
+    if let Some(guild_id) = ctx.guild_id() {
+        deleted = repo
+            .delete_macro(user_id, Some(guild_id.get()), &name)
+            .await?;
+    }
+    if !deleted {
+        deleted = repo.delete_macro(user_id, None, &name).await?;
+    }
+
+    if !deleted {
+        ctx.send(embed_error(
+            "Macro not found",
+            format!("You have no macro `${name}` here."),
+        ))
+        .await?;
+        return Ok(());
+    }
+    ctx.send(
+        CreateReply::default()
+            .content(format!("Deleted `${name}`"))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// The caller's macros that `$name` can refer to here, server ones taking
+/// precedence.
+pub(super) async fn user_macros(
+    repo: &MacroRepo,
+    user_id: u64,
+    guild_id: Option<u64>,
+) -> Result<Macros> {
+    let macros = repo.macros(user_id, guild_id).await?;
+    let (server, everywhere): (Vec<_>, Vec<_>) =
+        macros.into_iter().partition(|m| m.guild_id.is_some());
+
+    let mut parsed = Macros::new();
+    for m in everywhere.into_iter().chain(server) {
+        match dice::parse_labelled(&m.notation) {
+            Ok(notation) => {
+                parsed.insert(m.name, notation);
+            }
+            Err(e) => tracing::warn!("macro {} no longer parses: {e}", m.id),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Names of the caller's macros, without duplicates.
+async fn macro_names(ctx: Context<'_>) -> Vec<String> {
+    let macros = ctx
+        .data()
+        .macro_repo
+        .macros(ctx.author().id.get(), ctx.guild_id().map(|id| id.get()))
+        .await;
+    let mut names: Vec<_> = match macros {
+        Ok(macros) => macros
+            .into_iter()
+            .map(|m: roll_macro::Model| m.name)
+            .collect(),
+        Err(e) => {
+            tracing::warn!("could not look up macros to autocomplete: {e}");
+            vec![]
+        }
+    };
+    names.dedup();
+    names
+}
+
+async fn autocomplete_name(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.trim_start_matches('$').to_ascii_lowercase();
+    macro_names(ctx)
+        .await
+        .into_iter()
+        .filter(|name| name.starts_with(&partial))
+        .take(MAX_CHOICES)
+        .collect()
+}
+
+/// Complete a `$name` being typed at the end of a notation.
+pub(super) async fn autocomplete_notation(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(dollar) = partial.rfind('$') else {
+        return vec![];
+    };
+    let (before, typed) = (&partial[..dollar], &partial[dollar + 1..]);
+    if !typed.is_empty() && !dice::is_macro_name(typed) {
+        return vec![];
+    }
+    let typed = typed.to_ascii_lowercase();
+    macro_names(ctx)
+        .await
+        .into_iter()
+        .filter(|name| name.starts_with(&typed))
+        .map(|name| format!("{before}${name}"))
+        .filter(|choice| choice.chars().count() <= MAX_CHOICE_LEN)
+        .take(MAX_CHOICES)
+        .collect()
+}
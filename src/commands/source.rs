@@ -0,0 +1,76 @@
+use poise::{ChoiceParameter, CreateReply};
+
+use crate::repo::random::SourceKind;
+
+use super::{roll::source_kind, Context, Result};
+
+/// The sources a server may pick, every [`SourceKind`] but the seeded one.
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum GuildSource {
+    #[name = "Randomness beacon"]
+    Beacon,
+    #[name = "Randomness beacon, stretched"]
+    Stretched,
+    #[name = "OS random"]
+    Os,
+}
+
+impl From<GuildSource> for SourceKind {
+    fn from(source: GuildSource) -> Self {
+        match source {
+            GuildSource::Beacon => SourceKind::Beacon,
+            GuildSource::Stretched => SourceKind::Stretched,
+            GuildSource::Os => SourceKind::Os,
+        }
+    }
+}
+
+/// Set where the randomness of rolls in this server comes from
+#[poise::command(slash_command, guild_only, default_member_permissions = "MANAGE_GUILD")]
+pub async fn rollsource(
+    ctx: Context<'_>,
+    #[description = "Random source of rolls in this server"] source: Option<GuildSource>,
+    #[description = "Go back to the bot's default source"] clear: Option<bool>,
+) -> Result<()> {
+    let repo = &ctx.data().roll_repo;
+    let guild_id = ctx.guild_id().map(|id| id.get());
+
+    if let Some(guild_id) = guild_id {
+        if let Some(source) = source {
+            let source = SourceKind::from(source);
+            repo.set_guild_source(guild_id, Some(source.key().into()))
+                .await?;
+        } else if clear == Some(true) {
+            repo.set_guild_source(guild_id, None).await?;
+        }
+    }
+
+    let source = source_kind(ctx.data(), guild_id).await?;
+    let mut text = format!("Rolls in this server use the **{}**.", source.name());
+    if !source.verifiable() {
+        text.push_str(" They cannot be checked with `/verify`.");
+    }
+    ctx.send(CreateReply::default().content(text).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::ChoiceParameter;
+
+    use crate::repo::random::SourceKind;
+
+    use super::GuildSource;
+
+    #[test]
+    fn test_guild_sources() {
+        for source in [GuildSource::Beacon, GuildSource::Stretched, GuildSource::Os] {
+            let kind = SourceKind::from(source);
+            assert!(kind.guild_selectable());
+            assert_eq!(source.name(), kind.name());
+        }
+        assert!(!SourceKind::Seeded.guild_selectable());
+    }
+}
@@ -0,0 +1,150 @@
+use poise::{
+    serenity_prelude::{Colour, CreateEmbed},
+    ChoiceParameter, CreateReply,
+};
+
+use crate::{
+    dice::{self, DiceErr},
+    repo::{
+        nist_beacon::{BitSpan, NistBeaconRepoErr},
+        random::SourceKind,
+        roll::RollLogFilter,
+    },
+};
+
+use super::{
+    roll::{embed_error, format_rolled, log_dice},
+    Context, Result,
+};
+
+const COLOUR_VERIFIED: Colour = Colour::from_rgb(0, 200, 83);
+const COLOUR_MISMATCH: Colour = Colour::from_rgb(255, 0, 0);
+
+/// Recompute a roll from the beacon pulses it drew from
+#[poise::command(slash_command)]
+pub async fn verify(
+    ctx: Context<'_>,
+    #[description = "Roll ID, from the footer of the roll"] id: i32,
+) -> Result<()> {
+    let filter = RollLogFilter {
+        guild_id: ctx.guild_id().map(|id| id.get()),
+        channel_id: ctx.guild_id().is_none().then(|| ctx.channel_id().get()),
+        viewer_id: ctx.author().id.get(),
+        ..Default::default()
+    };
+    let Some(entry) = ctx.data().roll_repo.roll_log_entry(id, &filter).await? else {
+        ctx.send(embed_error(
+            "Roll not found",
+            format!("There is no roll {id} that you can see here."),
+        ))
+        .await?;
+        return Ok(());
+    };
+    if let Some(source) = entry.source.as_deref() {
+        let kind = SourceKind::from_key(source);
+        if !kind.is_some_and(SourceKind::verifiable) {
+            let name = kind.map_or(source, |kind| kind.name());
+            ctx.send(embed_error(
+                "Roll cannot be verified",
+                format!("Roll {id} was made with the {name}, not a randomness beacon."),
+            ))
+            .await?;
+            return Ok(());
+        }
+    }
+    let Some(spans) = entry
+        .draws
+        .as_deref()
+        .and_then(|d| serde_json::from_str::<Vec<BitSpan>>(d).ok())
+    else {
+        ctx.send(embed_error(
+            "Roll cannot be verified",
+            format!("Roll {id} was made before rolls recorded their beacon bits."),
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    // Don't leak a hidden roll into the channel
+    let hidden = entry.visibility != "public";
+    let notation = dice::parse(&entry.notation)?;
+    let mut replay = match ctx.data().nist_repo.replay(&spans).await {
+        Ok(replay) => replay,
+        Err(NistBeaconRepoErr::ReplayErr(e)) => {
+            ctx.send(embed_mismatch(
+                id,
+                format!("Could not replay the bits: {e}."),
+                hidden,
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let rolled = match notation.roll(&mut replay).await {
+        Ok(rolled) => rolled,
+        Err(DiceErr::NistBeaconRepoErr(NistBeaconRepoErr::ReplayErr(e))) => {
+            ctx.send(embed_mismatch(
+                id,
+                format!("Could not replay the bits: {e}."),
+                hidden,
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let bits: usize = spans.iter().map(|s| s.len as usize).sum();
+    let pulses = replay
+        .uris()
+        .iter()
+        .map(|uri| format!("- {uri}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = format!(
+        "`{}` by <@{}>, logged at {}\n\n{}\n\nRecomputed from {bits} bit(s) of:\n{pulses}",
+        entry.notation,
+        entry.user_id,
+        entry.created_at,
+        format_rolled(&notation, &rolled),
+    );
+
+    let matches =
+        rolled.total == entry.total && log_dice(&rolled) == entry.dice && replay.remaining() == 0;
+    let reply = if matches {
+        CreateEmbed::default()
+            .color(COLOUR_VERIFIED)
+            .title(format!("Roll {id} verified ✅"))
+            .description(text)
+    } else {
+        CreateEmbed::default()
+            .color(COLOUR_MISMATCH)
+            .title(format!("Roll {id} does not match ❌"))
+            .description(format!(
+                "The logged total was **{}**, recomputing gives:\n\n{text}",
+                entry.total
+            ))
+    };
+    ctx.send(
+        CreateReply::default()
+            .embed(reply)
+            .reply(true)
+            .ephemeral(hidden),
+    )
+    .await?;
+
+    Ok(())
+}
+
+fn embed_mismatch(id: i32, text: String, hidden: bool) -> CreateReply {
+    CreateReply::default()
+        .embed(
+            CreateEmbed::default()
+                .color(COLOUR_MISMATCH)
+                .title(format!("Roll {id} does not match ❌"))
+                .description(text),
+        )
+        .reply(true)
+        .ephemeral(hidden)
+}
@@ -0,0 +1,260 @@
+use poise::{
+    serenity_prelude::{
+        self as serenity, ButtonStyle, ChannelId, Colour, ComponentInteraction, CreateActionRow,
+        CreateButton, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage, CreateMessage, UserId,
+    },
+    ChoiceParameter, CreateReply,
+};
+
+use crate::repo::roll::NewHiddenRoll;
+
+use super::{
+    roll::{embed_error, embed_roll},
+    Context, Data, Result,
+};
+
+const REVEAL_ID_PREFIX: &str = "reveal_roll:";
+
+/// Who gets to see the result of a `/roll`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum Visibility {
+    #[default]
+    Public,
+    /// Only the roller, as an ephemeral reply
+    #[name = "Self"]
+    Private,
+    /// The roller and the GM, by DM
+    #[name = "GM only"]
+    Gm,
+    /// Only the GM, not even the roller
+    Blind,
+}
+
+impl Visibility {
+    /// Stable name stored in the database.
+    pub fn key(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Private => "self",
+            Visibility::Gm => "gm",
+            Visibility::Blind => "blind",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Some(match key {
+            "public" => Visibility::Public,
+            "self" => Visibility::Private,
+            "gm" => Visibility::Gm,
+            "blind" => Visibility::Blind,
+            _ => return None,
+        })
+    }
+}
+
+/// Set the default roll visibility and the GM of this channel
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn rollsettings(
+    ctx: Context<'_>,
+    #[description = "Default visibility of /roll in this channel"] visibility: Option<Visibility>,
+    #[description = "Who receives GM-only and blind rolls"] gm: Option<serenity::User>,
+    #[description = "Forget the GM of this channel"] clear_gm: Option<bool>,
+) -> Result<()> {
+    let repo = &ctx.data().roll_repo;
+    let channel_id = ctx.channel_id().get();
+
+    if let Some(v) = visibility {
+        repo.set_channel_visibility(channel_id, Some(v.key().into()))
+            .await?;
+    }
+    if let Some(gm) = gm {
+        repo.set_channel_gm(channel_id, Some(gm.id.get())).await?;
+    } else if clear_gm == Some(true) {
+        repo.set_channel_gm(channel_id, None).await?;
+    }
+
+    let setting = repo.channel_setting(channel_id).await?;
+    let visibility = setting
+        .as_ref()
+        .and_then(|s| Visibility::from_key(s.roll_visibility.as_deref()?))
+        .unwrap_or_default();
+    let gm = match setting.and_then(|s| s.gm_id) {
+        Some(id) => format!("<@{id}>"),
+        None => "none".into(),
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Rolls in this channel are **{}** by default. GM: {gm}",
+                visibility.name()
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Keep a roll out of the channel, saving it so it can be revealed later.
+///
+/// GM-only and blind rolls need `gm_id`.
+pub async fn send_hidden(
+    ctx: Context<'_>,
+    visibility: Visibility,
+    gm_id: Option<u64>,
+    title: &str,
+    text: String,
+    colour: Colour,
+    footer: String,
+) -> Result<()> {
+    let author = ctx.author().id;
+    let id = ctx
+        .data()
+        .roll_repo
+        .save_hidden_roll(NewHiddenRoll {
+            guild_id: ctx.guild_id().map(|id| id.get()),
+            channel_id: ctx.channel_id().get(),
+            user_id: author.get(),
+            gm_id,
+            visibility: visibility.key().into(),
+            title: title.into(),
+            description: text.clone(),
+            colour: colour.0,
+            footer: Some(footer.clone()),
+        })
+        .await?;
+
+    if visibility == Visibility::Private {
+        let embed = embed_roll(title, text, colour).footer(CreateEmbedFooter::new(footer));
+        ctx.send(
+            CreateReply::default()
+                .embed(embed)
+                .components(vec![reveal_button(id)])
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let gm_id = UserId::new(gm_id.expect("GM-only and blind rolls have a GM"));
+    let dm = CreateMessage::new()
+        .content(format!(
+            "{} roll by <@{author}> in <#{}>",
+            visibility.name(),
+            ctx.channel_id()
+        ))
+        .embed(embed_roll(title, text.clone(), colour).footer(CreateEmbedFooter::new(&footer)))
+        .components(vec![reveal_button(id)]);
+    if let Err(e) = gm_id.direct_message(ctx, dm).await {
+        tracing::warn!("could not DM hidden roll {id} to the GM: {e}");
+        ctx.send(embed_error(
+            "Could not reach the GM",
+            format!("<@{gm_id}> does not accept DMs from this bot."),
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let reply = match visibility {
+        Visibility::Blind => embed_roll(title, "Only the GM sees this roll.".into(), colour),
+        _ => embed_roll(title, text, colour)
+            .footer(CreateEmbedFooter::new(format!("{footer} · Sent to the GM"))),
+    };
+    ctx.send(CreateReply::default().embed(reply).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+fn reveal_button(id: i32) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(format!("{REVEAL_ID_PREFIX}{id}"))
+        .label("Reveal")
+        .style(ButtonStyle::Primary)])
+}
+
+/// Post a hidden roll into its channel when its Reveal button is clicked.
+///
+/// Only the GM may reveal, or the roller for rolls they kept to themselves.
+pub async fn handle_reveal(
+    ctx: &serenity::Context,
+    data: &Data,
+    interaction: &ComponentInteraction,
+) -> Result<()> {
+    let Some(Ok(id)) = interaction
+        .data
+        .custom_id
+        .strip_prefix(REVEAL_ID_PREFIX)
+        .map(str::parse)
+    else {
+        return Ok(());
+    };
+
+    let respond = |text: &str| {
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(text)
+                .ephemeral(true),
+        )
+    };
+
+    let Some(roll) = data.roll_repo.hidden_roll(id).await? else {
+        interaction
+            .create_response(ctx, respond("This roll no longer exists."))
+            .await?;
+        return Ok(());
+    };
+
+    let user = interaction.user.id.get() as i64;
+    let own_roll = roll.user_id == user && roll.visibility == Visibility::Private.key();
+    if roll.gm_id != Some(user) && !own_roll {
+        interaction
+            .create_response(ctx, respond("Only the GM can reveal this roll."))
+            .await?;
+        return Ok(());
+    }
+    if !data.roll_repo.mark_revealed(id).await? {
+        interaction
+            .create_response(ctx, respond("This roll was already revealed."))
+            .await?;
+        return Ok(());
+    }
+
+    let visibility = Visibility::from_key(&roll.visibility).unwrap_or_default();
+    let embed = embed_roll(
+        &roll.title,
+        roll.description,
+        Colour::new(roll.colour as u32),
+    )
+    .footer(CreateEmbedFooter::new(match roll.footer {
+        Some(footer) => format!("{footer} · Revealed by {}", interaction.user.name),
+        None => format!("Revealed by {}", interaction.user.name),
+    }));
+    ChannelId::new(roll.channel_id as u64)
+        .send_message(
+            ctx,
+            CreateMessage::new()
+                .content(format!(
+                    "{} roll by <@{}>, revealed",
+                    visibility.name(),
+                    roll.user_id
+                ))
+                .embed(embed),
+        )
+        .await?;
+
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new().components(vec![]),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
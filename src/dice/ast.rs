@@ -0,0 +1,401 @@
+use std::fmt;
+
+/// A parsed dice expression.
+///
+/// Dice groups are stored in the order they appear in the notation and the
+/// expression tree refers to them by index, so that every group can be rolled
+/// (or analyzed) on its own before the arithmetic is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notation {
+    pub expr: Expr,
+    pub dice: Vec<Dice>,
+    /// The number the total is checked against, e.g. `vs 15`
+    pub target: Option<Compare>,
+    /// Free text after `#`, e.g. `# Perception`
+    pub label: Option<String>,
+}
+
+/// One `;` separated entry of a batch, rolled `times` times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repeated {
+    pub notation: Notation,
+    pub times: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    /// Index into [`Notation::dice`]
+    Dice(usize),
+    Neg(Box<Expr>),
+    Paren(Box<Expr>),
+    BinOp(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div(Rounding),
+    Rem,
+}
+
+/// How integer division rounds a non-exact quotient.
+///
+/// `/` rounds down, `/^` rounds up and `/~` rounds to the nearest integer
+/// (halves away from zero).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+    Nearest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dice {
+    pub faces: Faces,
+    pub count: usize,
+    pub keep: Option<KeepDrop>,
+    pub explode: Option<Explode>,
+    pub reroll: Option<Reroll>,
+    pub success: Option<Success>,
+    pub advantage: Option<Advantage>,
+}
+
+/// The faces of a single die.
+///
+/// A die is rolled by drawing a uniform index in `0..n_faces()` and mapping
+/// it through [`Faces::value`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Faces {
+    /// `dN`, numbered 1 to N
+    Range(i64),
+    /// `dF`, a Fate/Fudge die with faces -1, 0 and +1
+    Fate,
+    /// `d{1,1,2,3,5,8}`
+    Custom(Vec<i64>),
+    /// `d{hit,miss,crit}`, rolled as the index of the label. Labels carry no
+    /// numeric value, so these dice add nothing to a total.
+    Named(Vec<String>),
+}
+
+impl Faces {
+    pub fn n_faces(&self) -> i64 {
+        match self {
+            Faces::Range(n) => *n,
+            Faces::Fate => 3,
+            Faces::Custom(v) => v.len() as i64,
+            Faces::Named(v) => v.len() as i64,
+        }
+    }
+
+    /// Value of the face at `index`, counting from 0.
+    pub fn value(&self, index: i64) -> i64 {
+        match self {
+            Faces::Range(_) => index + 1,
+            Faces::Fate => index - 1,
+            Faces::Custom(v) => v[index as usize],
+            Faces::Named(_) => index,
+        }
+    }
+
+    pub fn lowest(&self) -> i64 {
+        match self {
+            Faces::Range(_) => 1,
+            Faces::Fate => -1,
+            Faces::Custom(v) => v.iter().copied().min().unwrap_or_default(),
+            Faces::Named(_) => 0,
+        }
+    }
+
+    pub fn highest(&self) -> i64 {
+        match self {
+            Faces::Range(n) => *n,
+            Faces::Fate => 1,
+            Faces::Custom(v) => v.iter().copied().max().unwrap_or_default(),
+            Faces::Named(v) => v.len() as i64 - 1,
+        }
+    }
+
+    /// Whether every face matches `c`.
+    pub fn all_match(&self, c: Compare) -> bool {
+        match self {
+            Faces::Range(n) => c.matches_all(1, *n),
+            _ => (0..self.n_faces()).all(|i| c.matches(self.value(i))),
+        }
+    }
+}
+
+/// `kh`/`kl` keep the highest/lowest `n` dice, `dh`/`dl` drop them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepDrop {
+    KeepHighest(usize),
+    KeepLowest(usize),
+    DropHighest(usize),
+    DropLowest(usize),
+}
+
+/// `!` explodes into an extra die, `!!` compounds the chain into a single die
+/// and `!p` penetrates (every extra roll counts one less, as in Hackmaster).
+///
+/// A die explodes when it matches `on`, or rolls its highest face by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explode {
+    pub kind: ExplodeKind,
+    pub on: Option<Compare>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplodeKind {
+    Explode,
+    Compound,
+    Penetrate,
+}
+
+/// `r` rerolls a die for as long as it matches `on`, `ro` rerolls it once.
+///
+/// Only the initial roll of each die is rerolled, not its explosions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reroll {
+    pub once: bool,
+    pub on: Compare,
+}
+
+/// Turns the dice into a pool: each kept die matching `on` is a success and
+/// each matching `fail` (`f`) a failure, and the pool is worth the net
+/// number of successes rather than the sum of its dice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Success {
+    pub on: Compare,
+    pub fail: Option<Compare>,
+}
+
+/// `adv`/`dis` roll a single die twice and keep the higher/lower one.
+///
+/// `b`/`p` (or `bonus`/`penalty`) are Call of Cthulhu bonus and penalty
+/// dice for `1d100`: `n` extra tens dice are rolled alongside the usual one
+/// and the lowest/highest result is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advantage {
+    Adv,
+    Dis,
+    Bonus(usize),
+    Penalty(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compare {
+    pub op: CmpOp,
+    pub value: i64,
+}
+
+impl Compare {
+    pub fn matches(self, x: i64) -> bool {
+        match self.op {
+            CmpOp::Eq => x == self.value,
+            CmpOp::Lt => x < self.value,
+            CmpOp::Le => x <= self.value,
+            CmpOp::Gt => x > self.value,
+            CmpOp::Ge => x >= self.value,
+        }
+    }
+
+    /// How far `x` lands from the value, positive on the passing side.
+    pub fn margin(self, x: i64) -> i64 {
+        match self.op {
+            CmpOp::Lt | CmpOp::Le => self.value.saturating_sub(x),
+            CmpOp::Eq | CmpOp::Gt | CmpOp::Ge => x.saturating_sub(self.value),
+        }
+    }
+
+    /// Whether every value in `lo..=hi` matches.
+    pub fn matches_all(self, lo: i64, hi: i64) -> bool {
+        match self.op {
+            CmpOp::Eq => lo == hi && lo == self.value,
+            CmpOp::Lt => hi < self.value,
+            CmpOp::Le => hi <= self.value,
+            CmpOp::Gt => lo > self.value,
+            CmpOp::Ge => lo >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.faces)?;
+        if let Some(r) = self.reroll {
+            write!(f, "{r}")?;
+        }
+        if let Some(e) = self.explode {
+            write!(f, "{e}")?;
+        }
+        if let Some(k) = self.keep {
+            write!(f, "{k}")?;
+        }
+        if let Some(s) = self.success {
+            write!(f, "{s}")?;
+        }
+        if let Some(a) = self.advantage {
+            write!(f, "{a}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for KeepDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepDrop::KeepHighest(n) => write!(f, "kh{n}"),
+            KeepDrop::KeepLowest(n) => write!(f, "kl{n}"),
+            KeepDrop::DropHighest(n) => write!(f, "dh{n}"),
+            KeepDrop::DropLowest(n) => write!(f, "dl{n}"),
+        }
+    }
+}
+
+impl fmt::Display for Faces {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Faces::Range(n) => write!(f, "{n}"),
+            Faces::Fate => f.write_str("F"),
+            Faces::Custom(v) => {
+                let v: Vec<_> = v.iter().map(|x| x.to_string()).collect();
+                write!(f, "{{{}}}", v.join(","))
+            }
+            Faces::Named(v) => write!(f, "{{{}}}", v.join(",")),
+        }
+    }
+}
+
+impl fmt::Display for Explode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.kind {
+            ExplodeKind::Explode => "!",
+            ExplodeKind::Compound => "!!",
+            ExplodeKind::Penetrate => "!p",
+        })?;
+        if let Some(on) = self.on {
+            write!(f, "{on}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Reroll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = if self.once { "ro" } else { "r" };
+        write!(f, "{r}{}", self.on)
+    }
+}
+
+impl fmt::Display for Success {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.on)?;
+        if let Some(fail) = self.fail {
+            write!(f, "f{fail}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Advantage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Advantage::Adv => f.write_str("adv"),
+            Advantage::Dis => f.write_str("dis"),
+            Advantage::Bonus(n) => write!(f, "b{n}"),
+            Advantage::Penalty(n) => write!(f, "p{n}"),
+        }
+    }
+}
+
+impl fmt::Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.op, self.value)
+    }
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CmpOp::Eq => "=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        })
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div(Rounding::Down) => "/",
+            Op::Div(Rounding::Up) => "/^",
+            Op::Div(Rounding::Nearest) => "/~",
+            Op::Rem => "%",
+        };
+        f.write_str(s)
+    }
+}
+
+impl Expr {
+    /// Render the expression, substituting each dice group with `dice(i)`.
+    pub fn render(&self, dice: &impl Fn(usize) -> String) -> String {
+        match self {
+            Expr::Num(x) => x.to_string(),
+            Expr::Dice(i) => dice(*i),
+            Expr::Neg(e) => format!("-{}", e.render(dice)),
+            Expr::Paren(e) => format!("({})", e.render(dice)),
+            Expr::BinOp(op, l, r) => format!("{} {} {}", l.render(dice), op, r.render(dice)),
+        }
+    }
+
+    /// Whether the expression is nothing more than a single dice group.
+    pub fn is_single_dice(&self) -> bool {
+        matches!(self, Expr::Dice(_))
+    }
+}
+
+impl Notation {
+    /// The same notation with advantage on every plain `1d20`, or `None` if
+    /// there is no such die to give it to.
+    pub fn with_advantage(&self) -> Option<Notation> {
+        let mut n = self.clone();
+        let mut changed = false;
+        for d in &mut n.dice {
+            if d.faces == Faces::Range(20)
+                && d.count == 1
+                && d.keep.is_none()
+                && d.advantage != Some(Advantage::Adv)
+            {
+                d.advantage = Some(Advantage::Adv);
+                changed = true;
+            }
+        }
+        changed.then_some(n)
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expr.render(&|i| self.dice[i].to_string()))?;
+        if let Some(t) = self.target {
+            write!(f, " {} DC {}", t.op, t.value)?;
+        }
+        Ok(())
+    }
+}
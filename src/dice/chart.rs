@@ -0,0 +1,269 @@
+//! Bar charts of [`Distribution`]s, rendered to PNG in memory.
+//!
+//! Only digits and a few signs are ever drawn, so labels use a tiny built-in
+//! bitmap font instead of pulling in a font renderer.
+
+use super::Distribution;
+
+const WIDTH: usize = 800;
+const HEIGHT: usize = 400;
+const MARGIN_LEFT: usize = 64;
+const MARGIN_RIGHT: usize = 16;
+const MARGIN_TOP: usize = 20;
+const MARGIN_BOTTOM: usize = 32;
+/// Outcomes are grouped into ranges past this many bars
+const MAX_BARS: usize = 160;
+/// Horizontal grid lines above the axis
+const Y_TICKS: usize = 4;
+
+type Rgb = [u8; 3];
+
+const BACKGROUND: Rgb = [255, 255, 255];
+const AXIS: Rgb = [64, 64, 64];
+const GRID: Rgb = [224, 224, 224];
+/// Colours of the first and second distribution
+const SERIES: [Rgb; 2] = [[31, 119, 180], [255, 127, 14]];
+
+/// Glyphs 3 pixels wide and 5 high, one row of bits per byte
+const FONT: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+];
+const FONT_SCALE: usize = 2;
+/// Width of a glyph and the gap after it
+const GLYPH_ADVANCE: usize = 4 * FONT_SCALE;
+
+/// Outcomes grouped into equally wide bars.
+#[derive(Debug, PartialEq)]
+struct Bins {
+    /// Smallest outcome of the first bar
+    lo: i64,
+    /// Outcomes per bar
+    width: u64,
+    /// Probability of each bar, per distribution
+    probs: Vec<Vec<f64>>,
+}
+
+impl Bins {
+    fn new(dists: &[&Distribution]) -> Self {
+        let lo = dists.iter().map(|d| d.min()).min().unwrap_or(0);
+        let hi = dists.iter().map(|d| d.max()).max().unwrap_or(0);
+        let n = (hi as i128 - lo as i128 + 1) as u128;
+        let width = n.div_ceil(MAX_BARS as u128) as u64;
+        let bars = n.div_ceil(width as u128) as usize;
+        let probs = dists
+            .iter()
+            .map(|d| {
+                let mut probs = vec![0.0; bars];
+                for (x, p) in d.iter() {
+                    probs[((x as i128 - lo as i128) as u128 / width as u128) as usize] += p;
+                }
+                probs
+            })
+            .collect();
+        Self { lo, width, probs }
+    }
+
+    fn bars(&self) -> usize {
+        self.probs.first().map_or(0, Vec::len)
+    }
+
+    /// The smallest outcome of a bar.
+    fn start(&self, bar: usize) -> i64 {
+        (self.lo as i128 + bar as i128 * self.width as i128) as i64
+    }
+}
+
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Self {
+            pixels: BACKGROUND.repeat(WIDTH * HEIGHT),
+        }
+    }
+
+    /// Blend `colour` over the pixels in `x0..x1`, `y0..y1`.
+    fn fill(
+        &mut self,
+        (x0, x1): (usize, usize),
+        (y0, y1): (usize, usize),
+        colour: Rgb,
+        alpha: f64,
+    ) {
+        for y in y0..y1.min(HEIGHT) {
+            for x in x0..x1.min(WIDTH) {
+                let i = (y * WIDTH + x) * 3;
+                for (c, new) in self.pixels[i..i + 3].iter_mut().zip(colour) {
+                    *c = (f64::from(*c) * (1.0 - alpha) + f64::from(new) * alpha).round() as u8;
+                }
+            }
+        }
+    }
+
+    /// Draw `text` with its top left corner at `x`, `y`.
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let Some((_, rows)) = FONT.iter().find(|(g, _)| *g == c) else {
+                continue;
+            };
+            let gx = x + i * GLYPH_ADVANCE;
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        let px = gx + col * FONT_SCALE;
+                        let py = y + row * FONT_SCALE;
+                        self.fill((px, px + FONT_SCALE), (py, py + FONT_SCALE), AXIS, 1.0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode(self) -> Vec<u8> {
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut w| w.write_image_data(&self.pixels))
+            .expect("encoding a valid image to memory does not fail");
+        png
+    }
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().count() * GLYPH_ADVANCE - FONT_SCALE
+}
+
+/// A percentage with as many decimals as its size needs.
+fn format_percent(p: f64) -> String {
+    let percent = p * 100.0;
+    if percent >= 10.0 {
+        format!("{percent:.0}%")
+    } else if percent >= 1.0 {
+        format!("{percent:.1}%")
+    } else {
+        format!("{percent:.2}%")
+    }
+}
+
+/// A PNG bar chart of up to two distributions, overlaid on the same axes.
+///
+/// Bars are coloured by [`SERIES`] in the order of `dists`.
+pub fn histogram(dists: &[&Distribution]) -> Vec<u8> {
+    let dists = &dists[..dists.len().min(SERIES.len())];
+    let bins = Bins::new(dists);
+    let mut canvas = Canvas::new();
+
+    let plot_w = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_h = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let axis_y = MARGIN_TOP + plot_h;
+    let y_max = bins
+        .probs
+        .iter()
+        .flatten()
+        .copied()
+        .fold(0.0, f64::max)
+        .max(f64::MIN_POSITIVE);
+
+    for tick in 1..=Y_TICKS {
+        let y = axis_y - plot_h * tick / Y_TICKS;
+        canvas.fill((MARGIN_LEFT, MARGIN_LEFT + plot_w), (y, y + 1), GRID, 1.0);
+        let label = format_percent(y_max * tick as f64 / Y_TICKS as f64);
+        let x = MARGIN_LEFT.saturating_sub(text_width(&label) + 6);
+        canvas.text(x, y.saturating_sub(5 * FONT_SCALE / 2), &label);
+    }
+
+    let bars = bins.bars().max(1);
+    let bar_edge = |bar: usize| MARGIN_LEFT + plot_w * bar / bars;
+    let alpha = if dists.len() > 1 { 0.6 } else { 1.0 };
+    for (probs, colour) in bins.probs.iter().zip(SERIES) {
+        for (bar, &p) in probs.iter().enumerate() {
+            let h = (p / y_max * plot_h as f64).round() as usize;
+            let (x0, x1) = (bar_edge(bar), bar_edge(bar + 1));
+            // Leave a gap between bars while they are wide enough to see it
+            let x1 = if x1 - x0 > 3 { x1 - 1 } else { x1 };
+            canvas.fill((x0, x1), (axis_y - h, axis_y), colour, alpha);
+        }
+    }
+    canvas.fill(
+        (MARGIN_LEFT, MARGIN_LEFT + plot_w),
+        (axis_y, axis_y + 1),
+        AXIS,
+        1.0,
+    );
+    canvas.fill(
+        (MARGIN_LEFT, MARGIN_LEFT + 1),
+        (MARGIN_TOP, axis_y),
+        AXIS,
+        1.0,
+    );
+
+    // Label every few bars, spaced so the widest label still fits
+    let widest = (0..bars)
+        .map(|bar| text_width(&bins.start(bar).to_string()))
+        .max()
+        .unwrap_or(0);
+    let bar_w = plot_w as f64 / bars as f64;
+    let step = ((widest + 2 * GLYPH_ADVANCE) as f64 / bar_w)
+        .ceil()
+        .max(1.0) as usize;
+    for bar in (0..bars).step_by(step) {
+        let label = bins.start(bar).to_string();
+        let centre = (bar_edge(bar) + bar_edge(bar + 1)) / 2;
+        canvas.fill((centre, centre + 1), (axis_y, axis_y + 4), AXIS, 1.0);
+        let x = centre.saturating_sub(text_width(&label) / 2);
+        canvas.text(x, axis_y + 8, &label);
+    }
+
+    canvas.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice::parse;
+
+    use super::{histogram, Bins, HEIGHT, MAX_BARS, WIDTH};
+
+    #[test]
+    fn test_bins() {
+        let a = parse("2d6").unwrap().odds().unwrap();
+        let b = parse("1d12").unwrap().odds().unwrap();
+        let bins = Bins::new(&[&a, &b]);
+        assert_eq!((bins.lo, bins.width, bins.bars()), (1, 1, 12));
+        assert_eq!(bins.probs[0][0], 0.0);
+        assert!((bins.probs[0][6] - 6.0 / 36.0).abs() < 1e-12);
+        assert!((bins.probs[1][0] - 1.0 / 12.0).abs() < 1e-12);
+
+        let wide = parse("1d1000").unwrap().odds().unwrap();
+        let bins = Bins::new(&[&wide]);
+        assert!(bins.bars() <= MAX_BARS);
+        assert!((bins.probs[0].iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(bins.start(1), 1 + bins.width as i64);
+    }
+
+    #[test]
+    fn test_histogram_png() {
+        let a = parse("2d6+3").unwrap().odds().unwrap();
+        let b = parse("1d12+3").unwrap().odds().unwrap();
+        let png = histogram(&[&a, &b]);
+        let decoder = png::Decoder::new(png.as_slice());
+        let info = decoder.read_info().unwrap().info().clone();
+        assert_eq!((info.width, info.height), (WIDTH as u32, HEIGHT as u32));
+    }
+}
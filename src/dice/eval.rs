@@ -0,0 +1,503 @@
+use crate::repo::nist_beacon::BitSource;
+
+use super::{
+    Advantage, CmpOp, Compare, Dice, DiceErr, ExplodeKind, Expr, Faces, KeepDrop, Notation, Op,
+    Result, Rounding,
+};
+
+/// Maximum number of rolls in a single explosion chain
+pub const MAX_EXPLODE_CHAIN: usize = 100;
+/// Maximum number of times `r` rerolls a single die
+pub const MAX_REROLLS: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct Die {
+    pub value: i64,
+    /// Raw rolls making up this die, more than one when compounded
+    pub rolls: Vec<i64>,
+    /// Values discarded by rerolling, in the order they were rolled
+    pub rerolled: Vec<i64>,
+    /// Added by the explosion of the previous die
+    pub exploded: bool,
+    pub dropped: bool,
+}
+
+impl Die {
+    fn new(value: i64) -> Self {
+        Self {
+            value,
+            rolls: vec![value],
+            rerolled: vec![],
+            exploded: false,
+            dropped: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiceRoll {
+    pub dice: Dice,
+    pub dice_rolled: Vec<Die>,
+}
+
+impl DiceRoll {
+    /// Net successes for a pool, otherwise the sum of the kept dice.
+    pub fn total(&self) -> Result<i64> {
+        if let Faces::Named(_) = self.dice.faces {
+            return Ok(0);
+        }
+        if let Some((successes, failures)) = self.successes() {
+            return Ok(successes as i64 - failures as i64);
+        }
+        self.kept()
+            .try_fold(0i64, |acc, d| acc.checked_add(d.value))
+            .ok_or(DiceErr::Overflow)
+    }
+
+    pub fn kept(&self) -> impl Iterator<Item = &Die> {
+        self.dice_rolled.iter().filter(|d| !d.dropped)
+    }
+
+    /// Number of `(successes, failures)` among the kept dice of a pool.
+    pub fn successes(&self) -> Option<(usize, usize)> {
+        let s = self.dice.success?;
+        let successes = self.kept().filter(|d| s.on.matches(d.value)).count();
+        let failures = match s.fail {
+            Some(f) => self.kept().filter(|d| f.matches(d.value)).count(),
+            None => 0,
+        };
+        Some((successes, failures))
+    }
+
+    /// Whether a pool rolled more failures than successes.
+    pub fn is_botch(&self) -> bool {
+        self.successes().is_some_and(|(s, f)| f > s)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rolled {
+    pub dice: Vec<DiceRoll>,
+    pub total: i64,
+}
+
+impl Dice {
+    async fn roll_face(&self, src: &mut impl BitSource) -> Result<i64> {
+        let i = src.rand(0, self.faces.n_faces() - 1).await?;
+        Ok(self.faces.value(i))
+    }
+
+    pub async fn roll(&self, src: &mut impl BitSource) -> Result<DiceRoll> {
+        let (count, keep) = match self.advantage {
+            Some(Advantage::Adv) => (2, Some(KeepDrop::KeepHighest(1))),
+            Some(Advantage::Dis) => (2, Some(KeepDrop::KeepLowest(1))),
+            Some(Advantage::Bonus(n)) => return self.roll_percentile(n, true, src).await,
+            Some(Advantage::Penalty(n)) => return self.roll_percentile(n, false, src).await,
+            None => (self.count, self.keep),
+        };
+
+        let mut dice_rolled = Vec::with_capacity(count);
+        // Plain dice take their bits in one go, in the same order as rolled
+        // one by one, so a pool is not interleaved with other rolls. Rerolls,
+        // explosions and each further group still draw die by die
+        if self.reroll.is_none() && self.explode.is_none() {
+            let faces = src.rand_many(count, 0, self.faces.n_faces() - 1).await?;
+            dice_rolled.extend(faces.into_iter().map(|i| Die::new(self.faces.value(i))));
+        } else {
+            for _ in 0..count {
+                let mut last = self.roll_face(src).await?;
+                let mut rerolled = vec![];
+                if let Some(r) = self.reroll {
+                    let max = if r.once { 1 } else { MAX_REROLLS };
+                    while r.on.matches(last) && rerolled.len() < max {
+                        rerolled.push(last);
+                        last = self.roll_face(src).await?;
+                    }
+                }
+                let mut die = Die::new(last);
+                die.rerolled = rerolled;
+
+                if let Some(e) = self.explode {
+                    let on = e.on.unwrap_or(Compare {
+                        op: CmpOp::Eq,
+                        value: self.faces.highest(),
+                    });
+                    let mut chain = 1;
+                    while on.matches(last) && chain < MAX_EXPLODE_CHAIN {
+                        last = self.roll_face(src).await?;
+                        chain += 1;
+                        match e.kind {
+                            ExplodeKind::Compound => {
+                                die.rolls.push(last);
+                                die.value = die.value.checked_add(last).ok_or(DiceErr::Overflow)?;
+                            }
+                            ExplodeKind::Explode | ExplodeKind::Penetrate => {
+                                dice_rolled.push(die);
+                                die = Die::new(last);
+                                die.exploded = true;
+                                if e.kind == ExplodeKind::Penetrate {
+                                    die.value -= 1;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                dice_rolled.push(die);
+            }
+        }
+        if let Some(k) = keep {
+            k.apply(&mut dice_rolled);
+        }
+        Ok(DiceRoll {
+            dice: self.clone(),
+            dice_rolled,
+        })
+    }
+
+    /// Roll `1d100` with `n` Call of Cthulhu bonus or penalty dice.
+    ///
+    /// A single units die is shared by `1 + n` tens dice, and each tens die
+    /// forms a candidate result (`00` + `0` reads as 100).
+    async fn roll_percentile(
+        &self,
+        n: usize,
+        bonus: bool,
+        src: &mut impl BitSource,
+    ) -> Result<DiceRoll> {
+        let digits = src.rand_many(n + 2, 0, 9).await?;
+        let units = digits[0];
+        let mut dice_rolled = Vec::with_capacity(n + 1);
+        for tens in &digits[1..] {
+            let tens = tens * 10;
+            let value = match tens + units {
+                0 => 100,
+                x => x,
+            };
+            dice_rolled.push(Die::new(value));
+        }
+
+        let keep = if bonus {
+            KeepDrop::KeepLowest(1)
+        } else {
+            KeepDrop::KeepHighest(1)
+        };
+        keep.apply(&mut dice_rolled);
+        Ok(DiceRoll {
+            dice: self.clone(),
+            dice_rolled,
+        })
+    }
+}
+
+impl KeepDrop {
+    /// Mark the dice that this modifier discards as dropped.
+    pub fn apply(self, dice: &mut [Die]) {
+        let mut order: Vec<usize> = (0..dice.len()).collect();
+        // Ascending by value, so the lowest dice come first
+        order.sort_by_key(|&i| dice[i].value);
+
+        let n = dice.len();
+        let drop = match self {
+            KeepDrop::KeepHighest(k) => &order[..n.saturating_sub(k)],
+            KeepDrop::KeepLowest(k) => &order[k.min(n)..],
+            KeepDrop::DropHighest(k) => &order[n.saturating_sub(k)..],
+            KeepDrop::DropLowest(k) => &order[..k.min(n)],
+        };
+        for &i in drop {
+            dice[i].dropped = true;
+        }
+    }
+}
+
+impl Notation {
+    /// Roll every dice group in order, then evaluate the expression.
+    pub async fn roll(&self, src: &mut impl BitSource) -> Result<Rolled> {
+        let mut dice = Vec::with_capacity(self.dice.len());
+        let mut totals = Vec::with_capacity(self.dice.len());
+        for d in &self.dice {
+            let r = d.roll(src).await?;
+            totals.push(r.total()?);
+            dice.push(r);
+        }
+
+        let total = self.expr.eval(&|i| totals[i])?;
+        Ok(Rolled { dice, total })
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression, substituting each dice group with `dice(i)`.
+    pub fn eval(&self, dice: &impl Fn(usize) -> i64) -> Result<i64> {
+        match self {
+            Expr::Num(x) => Ok(*x),
+            Expr::Dice(i) => Ok(dice(*i)),
+            Expr::Neg(e) => e.eval(dice)?.checked_neg().ok_or(DiceErr::Overflow),
+            Expr::Paren(e) => e.eval(dice),
+            Expr::BinOp(op, l, r) => op.apply(l.eval(dice)?, r.eval(dice)?),
+        }
+    }
+}
+
+impl Op {
+    pub fn apply(self, a: i64, b: i64) -> Result<i64> {
+        match self {
+            Op::Add => a.checked_add(b).ok_or(DiceErr::Overflow),
+            Op::Sub => a.checked_sub(b).ok_or(DiceErr::Overflow),
+            Op::Mul => a.checked_mul(b).ok_or(DiceErr::Overflow),
+            Op::Div(r) => div(a, b, r),
+            Op::Rem => {
+                if b == 0 {
+                    return Err(DiceErr::DivByZero);
+                }
+                a.checked_rem_euclid(b).ok_or(DiceErr::Overflow)
+            }
+        }
+    }
+}
+
+fn div(a: i64, b: i64, rounding: Rounding) -> Result<i64> {
+    if b == 0 {
+        return Err(DiceErr::DivByZero);
+    }
+    let q = a.checked_div(b).ok_or(DiceErr::Overflow)?;
+    let rem = a % b;
+    if rem == 0 {
+        return Ok(q);
+    }
+
+    // The exact quotient lies between q and q-1 when it is negative,
+    // and between q and q+1 otherwise.
+    let negative = (rem < 0) != (b < 0);
+    let away = if negative { q - 1 } else { q + 1 };
+    Ok(match rounding {
+        Rounding::Down if negative => away,
+        Rounding::Down => q,
+        Rounding::Up if negative => q,
+        Rounding::Up => away,
+        Rounding::Nearest if rem.unsigned_abs() * 2 >= b.unsigned_abs() => away,
+        Rounding::Nearest => q,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use bitvec::{order::Msb0, vec::BitVec};
+
+    use super::{div, DiceRoll, Die, MAX_EXPLODE_CHAIN, MAX_REROLLS};
+    use crate::{
+        dice::{parse, KeepDrop, Rounding},
+        repo::nist_beacon::{self, BitSource},
+    };
+
+    /// A source drawing the given numbers in order, each counted from the
+    /// start of the range it is drawn from.
+    struct Fixed(VecDeque<i64>);
+
+    impl BitSource for Fixed {
+        async fn pop_bits(&mut self, _: usize) -> nist_beacon::Result<BitVec<u8, Msb0>> {
+            unimplemented!("dice only draw whole numbers")
+        }
+
+        async fn rand(&mut self, from: i64, to: i64) -> nist_beacon::Result<i64> {
+            let x = self.0.pop_front().expect("the roll draws no more numbers");
+            assert!(
+                (0..=to - from).contains(&x),
+                "{x} is not in 0..={}",
+                to - from
+            );
+            Ok(from + x)
+        }
+    }
+
+    /// Roll the first dice group of `notation`, whose dice are numbered from
+    /// 1, as `faces` in order.
+    async fn roll(notation: &str, faces: &[i64]) -> DiceRoll {
+        let mut src = Fixed(faces.iter().map(|f| f - 1).collect());
+        let roll = parse(notation).unwrap().dice[0]
+            .roll(&mut src)
+            .await
+            .unwrap();
+        assert!(src.0.is_empty(), "{} number(s) left undrawn", src.0.len());
+        roll
+    }
+
+    fn values(roll: &DiceRoll) -> Vec<i64> {
+        roll.dice_rolled.iter().map(|d| d.value).collect()
+    }
+
+    fn dropped_after(k: KeepDrop, values: &[i64]) -> Vec<bool> {
+        let mut dice: Vec<_> = values.iter().map(|&x| Die::new(x)).collect();
+        k.apply(&mut dice);
+        dice.iter().map(|d| d.dropped).collect()
+    }
+
+    #[test]
+    fn test_keep_drop() {
+        let v = [3, 6, 1, 4];
+        assert_eq!(
+            dropped_after(KeepDrop::KeepHighest(3), &v),
+            [false, false, true, false]
+        );
+        assert_eq!(
+            dropped_after(KeepDrop::KeepLowest(1), &v),
+            [true, true, false, true]
+        );
+        assert_eq!(
+            dropped_after(KeepDrop::DropHighest(2), &v),
+            [false, true, false, true]
+        );
+        assert_eq!(
+            dropped_after(KeepDrop::DropLowest(2), &v),
+            [true, false, true, false]
+        );
+        assert_eq!(dropped_after(KeepDrop::KeepHighest(9), &v), [false; 4]);
+        assert_eq!(dropped_after(KeepDrop::DropLowest(9), &v), [true; 4]);
+    }
+
+    #[test]
+    fn test_div_rounding() {
+        assert_eq!(div(7, 2, Rounding::Down).unwrap(), 3);
+        assert_eq!(div(7, 2, Rounding::Up).unwrap(), 4);
+        assert_eq!(div(7, 2, Rounding::Nearest).unwrap(), 4);
+        assert_eq!(div(7, 3, Rounding::Nearest).unwrap(), 2);
+        assert_eq!(div(-7, 2, Rounding::Down).unwrap(), -4);
+        assert_eq!(div(-7, 2, Rounding::Up).unwrap(), -3);
+        assert_eq!(div(-7, 2, Rounding::Nearest).unwrap(), -4);
+        assert_eq!(div(7, -2, Rounding::Down).unwrap(), -4);
+        assert_eq!(div(6, 3, Rounding::Up).unwrap(), 2);
+        assert!(div(1, 0, Rounding::Down).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_explode() {
+        let r = roll("1d6!", &[6, 6, 3]).await;
+        assert_eq!(values(&r), [6, 6, 3]);
+        let exploded: Vec<_> = r.dice_rolled.iter().map(|d| d.exploded).collect();
+        assert_eq!(exploded, [false, true, true]);
+        assert_eq!(r.total().unwrap(), 15);
+
+        let r = roll("1d6!!", &[6, 6, 3]).await;
+        assert_eq!(values(&r), [15]);
+        assert_eq!(r.dice_rolled[0].rolls, [6, 6, 3]);
+
+        // Every roll after the first counts one less
+        let r = roll("1d6!p", &[6, 6, 3]).await;
+        assert_eq!(values(&r), [6, 5, 2]);
+        assert_eq!(r.dice_rolled[2].rolls, [3]);
+        assert_eq!(r.total().unwrap(), 13);
+
+        let r = roll("2d10!>=8", &[8, 3, 9, 10, 1]).await;
+        assert_eq!(values(&r), [8, 3, 9, 10, 1]);
+        assert_eq!(r.total().unwrap(), 31);
+        let r = roll("2d10!>=8", &[7, 2]).await;
+        assert_eq!(values(&r), [7, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_explode_cap() {
+        let sixes = [6; MAX_EXPLODE_CHAIN];
+        let r = roll("1d6!", &sixes).await;
+        assert_eq!(r.dice_rolled.len(), MAX_EXPLODE_CHAIN);
+        assert_eq!(r.total().unwrap(), 6 * MAX_EXPLODE_CHAIN as i64);
+
+        let r = roll("1d6!!", &sixes).await;
+        assert_eq!(r.dice_rolled.len(), 1);
+        assert_eq!(r.dice_rolled[0].rolls.len(), MAX_EXPLODE_CHAIN);
+
+        let r = roll("1d6!p", &sixes).await;
+        assert_eq!(r.total().unwrap(), 6 + 5 * (MAX_EXPLODE_CHAIN as i64 - 1));
+    }
+
+    #[tokio::test]
+    async fn test_pool() {
+        let r = roll("4d10>=7", &[7, 10, 2, 6]).await;
+        assert_eq!(r.successes(), Some((2, 0)));
+        assert_eq!(r.total().unwrap(), 2);
+        assert!(!r.is_botch());
+
+        let r = roll("6d6>=5f1", &[5, 6, 1, 1, 1, 3]).await;
+        assert_eq!(r.successes(), Some((2, 3)));
+        assert_eq!(r.total().unwrap(), -1);
+        assert!(r.is_botch());
+        // As many failures as successes is no botch
+        let r = roll("4d6>=5f1", &[5, 1, 3, 4]).await;
+        assert_eq!(r.total().unwrap(), 0);
+        assert!(!r.is_botch());
+
+        // Judged on what a die is worth, and only among the kept dice
+        let r = roll("2d6>=8!!", &[6, 3, 4]).await;
+        assert_eq!(r.successes(), Some((1, 0)));
+        let r = roll("3d10kh2>=7f1", &[1, 8, 9]).await;
+        assert_eq!(r.successes(), Some((2, 0)));
+    }
+
+    #[tokio::test]
+    async fn test_reroll() {
+        let r = roll("2d6r1", &[1, 1, 4, 2]).await;
+        assert_eq!(values(&r), [4, 2]);
+        assert_eq!(r.dice_rolled[0].rerolled, [1, 1]);
+        assert!(r.dice_rolled[1].rerolled.is_empty());
+
+        // Once, even when the new roll matches again
+        let r = roll("1d6ro<3", &[1, 2]).await;
+        assert_eq!(values(&r), [2]);
+        assert_eq!(r.dice_rolled[0].rerolled, [1]);
+
+        // Explosions are not rerolled
+        let r = roll("1d6r1!", &[1, 6, 1]).await;
+        assert_eq!(values(&r), [6, 1]);
+        assert_eq!(r.dice_rolled[0].rerolled, [1]);
+        assert!(r.dice_rolled[1].rerolled.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reroll_cap() {
+        let r = roll("1d6r<=5", &[1; MAX_REROLLS + 1]).await;
+        assert_eq!(r.dice_rolled[0].rerolled, [1; MAX_REROLLS]);
+        assert_eq!(values(&r), [1]);
+    }
+
+    #[tokio::test]
+    async fn test_percentile() {
+        // The units digit first, then each tens digit
+        let percentile = |notation: &'static str, digits: &'static [i64]| async move {
+            let mut src = Fixed(digits.iter().copied().collect());
+            let r = parse(notation).unwrap().dice[0]
+                .roll(&mut src)
+                .await
+                .unwrap();
+            assert!(src.0.is_empty());
+            let dropped: Vec<_> = r.dice_rolled.iter().map(|d| d.dropped).collect();
+            (values(&r), dropped, r.total().unwrap())
+        };
+
+        assert_eq!(
+            percentile("1d100b1", &[5, 3, 7]).await,
+            (vec![35, 75], vec![false, true], 35)
+        );
+        assert_eq!(
+            percentile("1d100p1", &[5, 3, 7]).await,
+            (vec![35, 75], vec![true, false], 75)
+        );
+        // 00 and 0 read as 100, the worst result
+        assert_eq!(
+            percentile("1d100b1", &[0, 0, 5]).await,
+            (vec![100, 50], vec![true, false], 50)
+        );
+        assert_eq!(
+            percentile("1d100p2", &[0, 4, 0, 9]).await,
+            (vec![40, 100, 90], vec![true, false, true], 100)
+        );
+    }
+
+    #[test]
+    fn test_eval_expr() {
+        let n = parse("2*(1d6+3) - 1d8/^2 % 3").unwrap();
+        let x = n.expr.eval(&|i| [4, 5][i]).unwrap();
+        assert_eq!(x, 14);
+        assert_eq!(parse("-(1d4) * -2").unwrap().expr.eval(&|_| 3).unwrap(), 6);
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use super::{CmpOp, ParseErr, ParseErrKind, Result, Rounding};
+
+/// Maximum length of a `# label`, well under Discord's embed title limit
+const MAX_LABEL_LEN: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Num(i64),
+    /// A run of ASCII letters, lowercased
+    Ident(String),
+    Plus,
+    Minus,
+    Star,
+    Slash(Rounding),
+    Percent,
+    LParen,
+    RParen,
+    Bang,
+    Cmp(CmpOp),
+    /// Comma separated faces between braces, as written
+    Faces(Vec<String>),
+    Comma,
+    Semicolon,
+    /// `# text`, up to the end of the entry
+    Label(String),
+    /// `$name` of a saved macro, lowercased
+    Macro(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(x) => write!(f, "number {x}"),
+            Token::Ident(s) => write!(f, "'{s}'"),
+            Token::Plus => f.write_str("'+'"),
+            Token::Minus => f.write_str("'-'"),
+            Token::Star => f.write_str("'*'"),
+            Token::Slash(r) => write!(f, "'{}'", super::Op::Div(*r)),
+            Token::Percent => f.write_str("'%'"),
+            Token::LParen => f.write_str("'('"),
+            Token::RParen => f.write_str("')'"),
+            Token::Bang => f.write_str("'!'"),
+            Token::Cmp(op) => write!(f, "'{op}'"),
+            Token::Faces(_) => f.write_str("faces"),
+            Token::Comma => f.write_str("','"),
+            Token::Semicolon => f.write_str("';'"),
+            Token::Label(_) => f.write_str("label"),
+            Token::Macro(name) => write!(f, "macro '${name}'"),
+        }
+    }
+}
+
+/// A token and the (char) position it starts at in the notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned {
+    pub pos: usize,
+    pub tok: Token,
+}
+
+pub fn tokenize(s: &str) -> Result<Vec<Spanned>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut toks = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = i;
+
+        let tok = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '0'..='9' => {
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let digits: String = chars[pos..i].iter().collect();
+                let x = digits
+                    .parse()
+                    .map_err(|_| ParseErr::new(pos, ParseErrKind::NumberTooLarge))?;
+                toks.push(Spanned {
+                    pos,
+                    tok: Token::Num(x),
+                });
+                continue;
+            }
+            c if c.is_ascii_alphabetic() => {
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let ident = chars[pos..i]
+                    .iter()
+                    .map(|c| c.to_ascii_lowercase())
+                    .collect();
+                toks.push(Spanned {
+                    pos,
+                    tok: Token::Ident(ident),
+                });
+                continue;
+            }
+            '+' => Token::Plus,
+            '-' | '−' => Token::Minus,
+            '*' | '×' => Token::Star,
+            '/' => match chars.get(i + 1) {
+                Some('^') => {
+                    i += 1;
+                    Token::Slash(Rounding::Up)
+                }
+                Some('~') => {
+                    i += 1;
+                    Token::Slash(Rounding::Nearest)
+                }
+                _ => Token::Slash(Rounding::Down),
+            },
+            '%' => Token::Percent,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '{' => {
+                let Some(len) = chars[i..].iter().position(|&c| c == '}') else {
+                    let err = ParseErr::new(pos, ParseErrKind::Invalid("unclosed '{'".into()))
+                        .with_suggestion(Some("close the faces with '}'".into()));
+                    return Err(err.into());
+                };
+                let inner: String = chars[i + 1..i + len].iter().collect();
+                let faces: Vec<String> = inner.split(',').map(|f| f.trim().to_string()).collect();
+                if faces.iter().any(String::is_empty) {
+                    let kind = ParseErrKind::Invalid("empty face between braces".into());
+                    return Err(ParseErr::new(pos, kind).into());
+                }
+                i += len;
+                Token::Faces(faces)
+            }
+            '#' => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|&c| c == ';')
+                    .unwrap_or(chars.len() - i);
+                let label: String = chars[i + 1..i + len].iter().collect();
+                let label = label.trim();
+                if label.is_empty() || label.chars().count() > MAX_LABEL_LEN {
+                    let kind = ParseErrKind::Invalid(format!(
+                        "label must be 1 to {MAX_LABEL_LEN} characters"
+                    ));
+                    return Err(ParseErr::new(pos, kind).into());
+                }
+                toks.push(Spanned {
+                    pos,
+                    tok: Token::Label(label.into()),
+                });
+                i += len;
+                continue;
+            }
+            '$' => {
+                i += 1;
+                while i < chars.len() && is_macro_char(chars[i]) {
+                    i += 1;
+                }
+                let name: String = chars[pos + 1..i]
+                    .iter()
+                    .map(|c| c.to_ascii_lowercase())
+                    .collect();
+                if name.is_empty() {
+                    let kind = ParseErrKind::Invalid("'$' must be followed by a macro name".into());
+                    return Err(ParseErr::new(pos, kind).into());
+                }
+                toks.push(Spanned {
+                    pos,
+                    tok: Token::Macro(name),
+                });
+                continue;
+            }
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '!' => Token::Bang,
+            '=' => Token::Cmp(CmpOp::Eq),
+            '<' | '>' => {
+                let or_eq = chars.get(i + 1) == Some(&'=');
+                if or_eq {
+                    i += 1;
+                }
+                Token::Cmp(match (c, or_eq) {
+                    ('<', false) => CmpOp::Lt,
+                    ('<', true) => CmpOp::Le,
+                    ('>', false) => CmpOp::Gt,
+                    _ => CmpOp::Ge,
+                })
+            }
+            c => {
+                let suggestion = match c {
+                    '[' | ']' => Some("use '(' and ')' for grouping".into()),
+                    _ => None,
+                };
+                let err =
+                    ParseErr::new(pos, ParseErrKind::UnexpectedChar(c)).with_suggestion(suggestion);
+                return Err(err.into());
+            }
+        };
+
+        toks.push(Spanned { pos, tok });
+        i += 1;
+    }
+
+    Ok(toks)
+}
+
+/// Whether `c` may appear in a macro name.
+pub fn is_macro_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
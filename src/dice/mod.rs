@@ -0,0 +1,91 @@
+mod ast;
+mod chart;
+pub use ast::{
+    Advantage, CmpOp, Compare, Dice, Explode, ExplodeKind, Expr, Faces, KeepDrop, Notation, Op,
+    Repeated, Reroll, Rounding, Success,
+};
+pub use chart::histogram;
+mod eval;
+pub use eval::{DiceRoll, Rolled};
+mod lexer;
+mod odds;
+pub use odds::Distribution;
+mod parser;
+use parser::MAX_DICE;
+pub use parser::{
+    is_macro_name, looks_like_dice, parse, parse_batch, parse_labelled, Macros, MAX_MACRO_NAME,
+};
+
+use crate::repo::nist_beacon::NistBeaconRepoErr;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiceErr {
+    #[error("DiceErr/ParseErr: {0}")]
+    ParseErr(#[from] ParseErr),
+    #[error("DiceErr/DivByZero")]
+    DivByZero,
+    #[error("DiceErr/Overflow")]
+    Overflow,
+    #[error("DiceErr/TooComplex")]
+    TooComplex,
+    #[error("DiceErr/Unsupported: {0}")]
+    Unsupported(String),
+    #[error("DiceErr/NistBeaconRepoErr: {0}")]
+    NistBeaconRepoErr(#[from] NistBeaconRepoErr),
+}
+
+pub type Result<T, E = DiceErr> = std::result::Result<T, E>;
+
+/// A notation that could not be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} at {pos}")]
+pub struct ParseErr {
+    /// Char position in the notation the error points at
+    pub pos: usize,
+    pub kind: ParseErrKind,
+    /// A "did you mean" hint, if there is a likely fix
+    pub suggestion: Option<String>,
+}
+
+impl ParseErr {
+    pub fn new(pos: usize, kind: ParseErrKind) -> Self {
+        Self {
+            pos,
+            kind,
+            suggestion: None,
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrKind {
+    #[error("unexpected character '{0}'")]
+    UnexpectedChar(char),
+    #[error("unexpected {0}")]
+    UnexpectedToken(String),
+    #[error("unexpected end of notation")]
+    UnexpectedEnd,
+    #[error("number is too large")]
+    NumberTooLarge,
+    #[error("a dice group can roll at most {MAX_DICE} dice")]
+    TooManyDice,
+    #[error("dice must roll at least one die")]
+    ZeroCount,
+    #[error("dice must have at least one face")]
+    ZeroFaces,
+    #[error("unknown modifier '{0}'")]
+    UnknownModifier(String),
+    #[error("modifier '{0}' is repeated")]
+    RepeatedModifier(String),
+    #[error("modifier '{0}' must be followed by a number")]
+    MissingArgument(String),
+    #[error("unknown macro '${0}'")]
+    UnknownMacro(String),
+    #[error("{0}")]
+    Invalid(String),
+}
@@ -0,0 +1,506 @@
+//! Exact probability distributions of dice expressions.
+//!
+//! Every dice group is independent of the others, so each is analyzed on its
+//! own and the expression tree then combines their distributions outcome by
+//! outcome, mirroring how [`Notation::roll`] evaluates a roll.
+
+use std::collections::BTreeMap;
+
+use super::{
+    eval::{MAX_EXPLODE_CHAIN, MAX_REROLLS},
+    Advantage, CmpOp, Compare, Dice, DiceErr, ExplodeKind, Expr, Faces, KeepDrop, Notation, Result,
+};
+
+/// Most distinct outcomes a distribution may have
+const MAX_OUTCOMES: usize = 100_000;
+/// Most multiply-adds a single analysis may take
+const MAX_WORK: u64 = 5_000_000;
+/// Explosion chains are cut once the chance of them going on drops below this
+const EXPLODE_EPSILON: f64 = 1e-12;
+
+/// Probability of each outcome, in ascending order of outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution(BTreeMap<i64, f64>);
+
+impl Distribution {
+    fn point(x: i64) -> Self {
+        Self(BTreeMap::from([(x, 1.0)]))
+    }
+
+    fn from_weights(weights: impl IntoIterator<Item = (i64, f64)>) -> Result<Self> {
+        let mut d = BTreeMap::new();
+        for (x, p) in weights {
+            if p > 0.0 {
+                *d.entry(x).or_insert(0.0) += p;
+            }
+        }
+        if d.len() > MAX_OUTCOMES {
+            return Err(DiceErr::TooComplex);
+        }
+        Ok(Self(d))
+    }
+
+    /// Each of `value(0..n)` equally likely.
+    fn uniform(n: i64, value: impl Fn(i64) -> i64) -> Result<Self> {
+        if n as usize > MAX_OUTCOMES {
+            return Err(DiceErr::TooComplex);
+        }
+        let p = 1.0 / n as f64;
+        Self::from_weights((0..n).map(|i| (value(i), p)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (i64, f64)> + '_ {
+        self.0.iter().map(|(&x, &p)| (x, p))
+    }
+
+    pub fn min(&self) -> i64 {
+        *self.0.keys().next().expect("a distribution has an outcome")
+    }
+
+    pub fn max(&self) -> i64 {
+        *self
+            .0
+            .keys()
+            .next_back()
+            .expect("a distribution has an outcome")
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.iter().map(|(x, p)| x as f64 * p).sum()
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        let var: f64 = self
+            .iter()
+            .map(|(x, p)| (x as f64 - mean).powi(2) * p)
+            .sum();
+        var.sqrt()
+    }
+
+    /// Probability of an outcome matching `pred`.
+    pub fn prob(&self, pred: impl Fn(i64) -> bool) -> f64 {
+        self.iter().filter(|&(x, _)| pred(x)).map(|(_, p)| p).sum()
+    }
+
+    /// The smallest outcome at least `q` of the rolls are at or under.
+    pub fn percentile(&self, q: f64) -> i64 {
+        let mut cdf = 0.0;
+        for (x, p) in self.iter() {
+            cdf += p;
+            // Leave room for rounding errors in the sum
+            if cdf >= q - 1e-9 {
+                return x;
+            }
+        }
+        self.max()
+    }
+
+    fn map(&self, f: impl Fn(i64) -> Result<i64>) -> Result<Self> {
+        let mut weights = Vec::with_capacity(self.0.len());
+        for (x, p) in self.iter() {
+            weights.push((f(x)?, p));
+        }
+        Self::from_weights(weights)
+    }
+
+    /// Distribution of `f(a, b)` for independent `a` and `b`.
+    fn combine(
+        &self,
+        other: &Self,
+        budget: &mut Budget,
+        f: impl Fn(i64, i64) -> Result<i64>,
+    ) -> Result<Self> {
+        budget.spend(self.0.len() as u64 * other.0.len() as u64)?;
+        let mut d = BTreeMap::new();
+        for (a, pa) in self.iter() {
+            for (b, pb) in other.iter() {
+                *d.entry(f(a, b)?).or_insert(0.0) += pa * pb;
+            }
+        }
+        if d.len() > MAX_OUTCOMES {
+            return Err(DiceErr::TooComplex);
+        }
+        Ok(Self(d))
+    }
+
+    /// Distribution of the sum of `n` independent copies.
+    fn sum_of(&self, n: usize, budget: &mut Budget) -> Result<Self> {
+        let mut sum = Self::point(0);
+        for _ in 0..n {
+            sum = sum.combine(self, budget, checked_add)?;
+        }
+        Ok(sum)
+    }
+}
+
+/// Caps the work an analysis does, so huge expressions fail fast rather
+/// than hold up the bot.
+struct Budget(u64);
+
+impl Budget {
+    fn spend(&mut self, work: u64) -> Result<()> {
+        self.0 = self.0.saturating_add(work);
+        if self.0 > MAX_WORK {
+            return Err(DiceErr::TooComplex);
+        }
+        Ok(())
+    }
+}
+
+fn checked_add(a: i64, b: i64) -> Result<i64> {
+    a.checked_add(b).ok_or(DiceErr::Overflow)
+}
+
+impl Notation {
+    /// Exact distribution of the total.
+    pub fn odds(&self) -> Result<Distribution> {
+        let mut budget = Budget(0);
+        let dice = self
+            .dice
+            .iter()
+            .map(|d| d.odds(&mut budget))
+            .collect::<Result<Vec<_>>>()?;
+        self.expr.odds(&dice, &mut budget)
+    }
+}
+
+impl Expr {
+    fn odds(&self, dice: &[Distribution], budget: &mut Budget) -> Result<Distribution> {
+        match self {
+            Expr::Num(x) => Ok(Distribution::point(*x)),
+            Expr::Dice(i) => Ok(dice[*i].clone()),
+            Expr::Neg(e) => e
+                .odds(dice, budget)?
+                .map(|x| x.checked_neg().ok_or(DiceErr::Overflow)),
+            Expr::Paren(e) => e.odds(dice, budget),
+            Expr::BinOp(op, l, r) => {
+                let (l, r) = (l.odds(dice, budget)?, r.odds(dice, budget)?);
+                l.combine(&r, budget, |a, b| op.apply(a, b))
+            }
+        }
+    }
+}
+
+impl Dice {
+    /// Exact distribution of the total of this group, as rolled by
+    /// [`Dice::roll`].
+    fn odds(&self, budget: &mut Budget) -> Result<Distribution> {
+        if let Faces::Named(_) = self.faces {
+            return Ok(Distribution::point(0));
+        }
+
+        let (count, keep) = match self.advantage {
+            Some(Advantage::Adv) => (2, Some(KeepDrop::KeepHighest(1))),
+            Some(Advantage::Dis) => (2, Some(KeepDrop::KeepLowest(1))),
+            Some(Advantage::Bonus(n)) => return percentile_odds(n, true, budget),
+            Some(Advantage::Penalty(n)) => return percentile_odds(n, false, budget),
+            None => (self.count, self.keep),
+        };
+
+        let raw = Distribution::uniform(self.faces.n_faces(), |i| self.faces.value(i))?;
+        let first = match self.reroll {
+            Some(r) => rerolled(&raw, r.on, if r.once { 1 } else { MAX_REROLLS })?,
+            None => raw.clone(),
+        };
+
+        // Each die counts as its value, or as a success/failure in a pool
+        let success = self.success;
+        let score = move |x: i64| -> Result<i64> {
+            Ok(match success {
+                Some(s) => {
+                    i64::from(s.on.matches(x)) - i64::from(s.fail.is_some_and(|f| f.matches(x)))
+                }
+                None => x,
+            })
+        };
+
+        let Some(explode) = self.explode else {
+            return match keep {
+                Some(k) => kept(&first, count, k, score, budget),
+                None => first.map(score)?.sum_of(count, budget),
+            };
+        };
+        let on = explode.on.unwrap_or(Compare {
+            op: CmpOp::Eq,
+            value: self.faces.highest(),
+        });
+
+        match explode.kind {
+            ExplodeKind::Compound => {
+                let die = chain(&first, &raw, on, Ok, Ok, budget)?;
+                match keep {
+                    Some(k) => kept(&die, count, k, score, budget),
+                    None => die.map(score)?.sum_of(count, budget),
+                }
+            }
+            ExplodeKind::Explode | ExplodeKind::Penetrate => {
+                if keep.is_some() {
+                    return Err(DiceErr::Unsupported(
+                        "keep/drop on dice that explode into extra dice".into(),
+                    ));
+                }
+                let penetrate = i64::from(explode.kind == ExplodeKind::Penetrate);
+                let next = |x: i64| score(x - penetrate);
+                chain(&first, &raw, on, score, next, budget)?.sum_of(count, budget)
+            }
+        }
+    }
+}
+
+/// Distribution of a die rerolled up to `max` times while it matches `on`.
+fn rerolled(raw: &Distribution, on: Compare, max: usize) -> Result<Distribution> {
+    let q = raw.prob(|x| on.matches(x));
+    // The first roll that doesn't match, or whatever the last reroll gives
+    let kept = (0..=max).map(|k| q.powi(k as i32)).sum::<f64>();
+    let last = q.powi(max as i32);
+    Distribution::from_weights(
+        raw.iter()
+            .map(|(x, p)| (x, if on.matches(x) { p * last } else { p * kept })),
+    )
+}
+
+/// Distribution of what an exploding chain adds up to.
+///
+/// The chain starts with a roll from `first`, worth `f_first(x)`, and each
+/// roll matching `on` adds a roll from `raw`, worth `f_next(x)`.
+fn chain(
+    first: &Distribution,
+    raw: &Distribution,
+    on: Compare,
+    f_first: impl Fn(i64) -> Result<i64>,
+    f_next: impl Fn(i64) -> Result<i64>,
+    budget: &mut Budget,
+) -> Result<Distribution> {
+    let p = raw.prob(|x| on.matches(x));
+    let mut rolls = 1;
+    while rolls < MAX_EXPLODE_CHAIN && p.powi(rolls as i32 - 1) >= EXPLODE_EPSILON {
+        rolls += 1;
+    }
+
+    // Build the chain from its last roll backwards
+    let mut tail = raw.map(&f_next)?;
+    for _ in 2..rolls {
+        tail = explode_into(raw, on, &f_next, &tail, budget)?;
+    }
+    if rolls == 1 {
+        return first.map(f_first);
+    }
+    explode_into(first, on, &f_first, &tail, budget)
+}
+
+/// A roll from `d` worth `f(x)`, plus a roll of `tail` whenever it matches.
+fn explode_into(
+    d: &Distribution,
+    on: Compare,
+    f: impl Fn(i64) -> Result<i64>,
+    tail: &Distribution,
+    budget: &mut Budget,
+) -> Result<Distribution> {
+    budget.spend(d.0.len() as u64 * tail.0.len() as u64)?;
+    let mut weights = vec![];
+    for (x, p) in d.iter() {
+        let v = f(x)?;
+        if on.matches(x) {
+            for (t, pt) in tail.iter() {
+                weights.push((checked_add(v, t)?, p * pt));
+            }
+        } else {
+            weights.push((v, p));
+        }
+    }
+    Distribution::from_weights(weights)
+}
+
+/// Distribution of the kept dice of `n` independent dice, each worth `f(x)`.
+///
+/// Outcomes are visited from the first kept end: whatever number of dice
+/// lands on each value, the first `k` dice seen are the kept ones.
+fn kept(
+    die: &Distribution,
+    n: usize,
+    keep: KeepDrop,
+    f: impl Fn(i64) -> Result<i64>,
+    budget: &mut Budget,
+) -> Result<Distribution> {
+    let (k, highest) = match keep {
+        KeepDrop::KeepHighest(k) => (k, true),
+        KeepDrop::KeepLowest(k) => (k, false),
+        KeepDrop::DropHighest(k) => (n.saturating_sub(k), false),
+        KeepDrop::DropLowest(k) => (n.saturating_sub(k), true),
+    };
+    let k = k.min(n);
+
+    // binom[i][j] = i choose j
+    let mut binom = vec![vec![1.0f64; n + 1]; n + 1];
+    for i in 1..=n {
+        for j in 1..i {
+            binom[i][j] = binom[i - 1][j - 1] + binom[i - 1][j];
+        }
+    }
+
+    let mut values: Vec<_> = die.iter().collect();
+    if highest {
+        values.reverse();
+    }
+
+    // by_used[u] maps the total of the kept dice to its probability, after
+    // `u` dice have been placed on the values visited so far
+    let mut by_used = vec![BTreeMap::new(); n + 1];
+    by_used[0].insert(0i64, 1.0);
+    for (x, p) in values {
+        let v = f(x)?;
+        let mut next = vec![BTreeMap::new(); n + 1];
+        for (used, totals) in by_used.iter().enumerate() {
+            budget.spend(totals.len() as u64 * (n - used + 1) as u64)?;
+            for (&total, &pr) in totals {
+                let mut pm = 1.0;
+                for m in 0..=n - used {
+                    let kept_here = m.min(k.saturating_sub(used)) as i64;
+                    let total = kept_here
+                        .checked_mul(v)
+                        .and_then(|x| x.checked_add(total))
+                        .ok_or(DiceErr::Overflow)?;
+                    *next[used + m].entry(total).or_insert(0.0) += pr * binom[n - used][m] * pm;
+                    pm *= p;
+                }
+            }
+        }
+        by_used = next;
+    }
+
+    Distribution::from_weights(std::mem::take(&mut by_used[n]))
+}
+
+/// Distribution of `1d100` with `n` Call of Cthulhu bonus or penalty dice.
+fn percentile_odds(n: usize, bonus: bool, budget: &mut Budget) -> Result<Distribution> {
+    let keep = if bonus {
+        KeepDrop::KeepLowest(1)
+    } else {
+        KeepDrop::KeepHighest(1)
+    };
+    let mut weights = vec![];
+    for units in 0..10 {
+        // The tens dice share the units die, so each units value is its own case
+        let tens = Distribution::uniform(10, |tens| match tens * 10 + units {
+            0 => 100,
+            x => x,
+        })?;
+        let d = kept(&tens, n + 1, keep, Ok, budget)?;
+        weights.extend(d.iter().map(|(x, p)| (x, p / 10.0)));
+    }
+    Distribution::from_weights(weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice::{parse, DiceErr};
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_odds_sums() {
+        let d = parse("2d6").unwrap().odds().unwrap();
+        assert_close(d.prob(|x| x == 7), 6.0 / 36.0);
+        assert_close(d.mean(), 7.0);
+        assert_close(d.std_dev(), (35.0f64 / 6.0).sqrt());
+        assert_eq!((d.min(), d.max()), (2, 12));
+        assert_eq!(d.percentile(0.5), 7);
+
+        let d = parse("1d4 * 2 - 1").unwrap().odds().unwrap();
+        assert_eq!(d.iter().map(|(x, _)| x).collect::<Vec<_>>(), [1, 3, 5, 7]);
+
+        let d = parse("4dF").unwrap().odds().unwrap();
+        assert_close(d.prob(|x| x == 4), 1.0 / 81.0);
+        assert_close(d.mean(), 0.0);
+    }
+
+    #[test]
+    fn test_odds_keep() {
+        let d = parse("4d6kh3").unwrap().odds().unwrap();
+        assert_close(d.mean(), 15869.0 / 1296.0);
+        assert_close(d.prob(|x| x == 18), 21.0 / 1296.0);
+        assert_eq!(d, parse("4d6dl1").unwrap().odds().unwrap());
+
+        let d = parse("1d20adv").unwrap().odds().unwrap();
+        assert_close(d.mean(), 13.825);
+        assert_close(d.prob(|x| x == 20), 39.0 / 400.0);
+
+        // Brute force every combination of units and two tens dice
+        let d = parse("1d100b1").unwrap().odds().unwrap();
+        let mut hits = 0;
+        for units in 0..10 {
+            for a in 0..10 {
+                for b in 0..10 {
+                    let value = |tens: i64| match tens * 10 + units {
+                        0 => 100,
+                        x => x,
+                    };
+                    if value(a).min(value(b)) <= 45 {
+                        hits += 1;
+                    }
+                }
+            }
+        }
+        assert_close(d.prob(|x| x <= 45), hits as f64 / 1000.0);
+    }
+
+    #[test]
+    fn test_odds_reroll_explode() {
+        let d = parse("1d6r1").unwrap().odds().unwrap();
+        assert_close(d.mean(), 4.0);
+        assert_close(d.prob(|x| x == 1), 6f64.powi(-100));
+
+        let d = parse("1d6ro1").unwrap().odds().unwrap();
+        assert_close(d.prob(|x| x == 1), 1.0 / 36.0);
+
+        // An exploding d6 averages 3.5 * 6/5
+        let d = parse("1d6!").unwrap().odds().unwrap();
+        assert_close(d.mean(), 4.2);
+        assert_close(d.prob(|x| x == 6), 0.0);
+        assert_close(d.prob(|x| x == 7), 1.0 / 36.0);
+        assert_eq!(d, parse("1d6!!").unwrap().odds().unwrap());
+
+        let d = parse("1d6!p").unwrap().odds().unwrap();
+        assert_close(d.prob(|x| x == 6), 1.0 / 36.0);
+
+        assert!(matches!(
+            parse("4d6!kh3").unwrap().odds(),
+            Err(DiceErr::Unsupported(_))
+        ));
+        assert!(parse("4d6!!kh3").unwrap().odds().is_ok());
+    }
+
+    #[test]
+    fn test_odds_pool() {
+        let d = parse("5d10>=8").unwrap().odds().unwrap();
+        assert_close(d.mean(), 1.5);
+        assert_close(d.prob(|x| x == 0), 0.7f64.powi(5));
+
+        let d = parse("1d10>=8f1").unwrap().odds().unwrap();
+        assert_close(d.prob(|x| x == -1), 0.1);
+        assert_close(d.prob(|x| x == 1), 0.3);
+
+        // Each 10 explodes into another die that may succeed again
+        let d = parse("1d10>=8!").unwrap().odds().unwrap();
+        assert_close(d.mean(), 0.3 / 0.9);
+    }
+
+    #[test]
+    fn test_odds_limits() {
+        assert!(matches!(
+            parse("1d2 / (1d2 - 1)").unwrap().odds(),
+            Err(DiceErr::DivByZero)
+        ));
+        assert!(matches!(
+            parse("200d1000000").unwrap().odds(),
+            Err(DiceErr::TooComplex)
+        ));
+        assert!(matches!(
+            parse("100d100+100d100").unwrap().odds(),
+            Err(DiceErr::TooComplex)
+        ));
+    }
+}
@@ -0,0 +1,1088 @@
+//! Recursive descent parser for dice notation.
+//!
+//! ```text
+//! batch := repeated (';' repeated)* ';'?
+//! repeated := (number 'x' notation | 'repeat' '(' number ',' notation ')' | notation) label?
+//! notation := expr target?
+//! target := 'vs' cmp? 'dc'? number | cmp 'dc'? number | 'dc' number
+//! label := '#' text
+//! expr  := term (('+' | '-') term)*
+//! term  := unary (('*' | '/' | '/^' | '/~' | '%') unary)*
+//! unary := '-' unary | atom
+//! atom  := dice | number | '(' expr ')' | '$' name
+//! dice  := number? ('d' faces | 'dF') modifier*
+//! faces := number | '{' number (',' number)* '}' | '{' label (',' label)* '}'
+//! modifier := ('k' | 'kh' | 'kl' | 'dh' | 'dl') number
+//!           | ('!' | '!!' | '!p') compare?
+//!           | ('r' | 'ro') compare
+//!           | ('=' | '<' | '<=' | '>' | '>=') number ('f' compare)?
+//!           | 'adv' | 'dis' | ('b' | 'bonus' | 'p' | 'penalty') number?
+//! compare := ('=' | '<' | '<=' | '>' | '>=')? number
+//! ```
+//!
+//! A `$name` stands for the expression of a saved macro. A macro making up
+//! a whole notation also brings its target and label along.
+
+use std::collections::HashMap;
+
+use super::{
+    lexer::{is_macro_char, tokenize, Spanned, Token},
+    Advantage, CmpOp, Compare, Dice, DiceErr, Explode, ExplodeKind, Expr, Faces, KeepDrop,
+    Notation, Op, ParseErr, ParseErrKind, Repeated, Reroll, Result, Success,
+};
+
+/// Maximum number of Call of Cthulhu bonus/penalty dice
+const MAX_BONUS_DICE: usize = 10;
+/// Maximum number of rows a batch may roll, counting every repetition
+const MAX_BATCH_ROWS: usize = 20;
+/// Maximum number of dice in a single dice group
+pub const MAX_DICE: usize = 200;
+
+/// Longest name a macro may have
+pub const MAX_MACRO_NAME: usize = 32;
+
+/// Every keyword the notation understands, for "did you mean" suggestions
+const KEYWORDS: &[&str] = &[
+    "d", "df", "k", "kh", "kl", "dh", "dl", "r", "ro", "f", "adv", "dis", "b", "bonus", "p",
+    "penalty", "vs", "dc", "x", "repeat",
+];
+
+/// Saved notations by name, for `$name` references
+pub type Macros = HashMap<String, Notation>;
+
+/// Parse a single expression.
+pub fn parse(notation: &str) -> Result<Notation> {
+    let macros = Macros::new();
+    let mut p = Parser::new(notation, &macros)?;
+
+    let n = p.notation()?;
+    if let Some(t) = p.toks.get(p.i) {
+        return Err(p.unexpected(t));
+    }
+
+    Ok(n)
+}
+
+/// Parse a single expression with an optional `# label`, as saved in a macro.
+pub fn parse_labelled(notation: &str) -> Result<Notation> {
+    let macros = Macros::new();
+    let mut p = Parser::new(notation, &macros)?;
+
+    let mut n = p.notation()?;
+    if let Some(Token::Label(label)) = p.peek() {
+        n.label = Some(label.clone());
+        p.i += 1;
+    }
+    if let Some(t) = p.toks.get(p.i) {
+        return Err(p.unexpected(t));
+    }
+
+    Ok(n)
+}
+
+/// Parse `;` separated expressions, each optionally repeated, resolving
+/// `$name` references against `macros`.
+pub fn parse_batch(notation: &str, macros: &Macros) -> Result<Vec<Repeated>> {
+    let mut p = Parser::new(notation, macros)?;
+    let mut batch = vec![];
+
+    loop {
+        batch.push(p.repetition()?);
+        match p.toks.get(p.i) {
+            None => break,
+            Some(Spanned {
+                tok: Token::Semicolon,
+                ..
+            }) => {
+                p.i += 1;
+                if p.peek().is_none() {
+                    break;
+                }
+            }
+            Some(t) => return Err(p.unexpected(t)),
+        }
+    }
+
+    let rows: usize = batch.iter().map(|r| r.times).sum();
+    if rows > MAX_BATCH_ROWS {
+        let kind = ParseErrKind::Invalid(format!(
+            "batch rolls {rows} rows, at most {MAX_BATCH_ROWS} are allowed"
+        ));
+        return Err(ParseErr::new(0, kind).into());
+    }
+
+    Ok(batch)
+}
+
+/// The keyword closest to `word`, if it is only a typo or two away.
+fn suggest(word: &str) -> Option<String> {
+    KEYWORDS
+        .iter()
+        .map(|kw| (edit_distance(word, kw), kw))
+        .filter(|(d, kw)| *d <= 1 || (*d == 2 && kw.len() > 3))
+        .min_by_key(|(d, kw)| (*d, kw.len().abs_diff(word.len())))
+        .map(|(_, kw)| format!("did you mean `{kw}`?"))
+}
+
+/// Levenshtein distance between two ASCII words.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.bytes().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = sub.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+/// Whether `name` can be referenced as `$name`.
+pub fn is_macro_name(name: &str) -> bool {
+    (1..=MAX_MACRO_NAME).contains(&name.len()) && name.chars().all(is_macro_char)
+}
+
+/// Whether `text` lexes as dice notation with a die or macro in it, to tell
+/// a notation with a typo from ordinary text.
+pub fn looks_like_dice(text: &str) -> bool {
+    tokenize(text).is_ok_and(|toks| {
+        toks.iter().any(|t| match &t.tok {
+            Token::Ident(word) => word == "d" || word == "df",
+            Token::Macro(_) => true,
+            _ => false,
+        })
+    })
+}
+
+/// The macro name closest to `name`, if it is only a typo or two away.
+fn suggest_macro(name: &str, macros: &Macros) -> Option<String> {
+    macros
+        .keys()
+        .map(|m| (edit_distance(name, m), m))
+        .filter(|(d, _)| *d <= 2)
+        .min()
+        .map(|(_, m)| format!("did you mean `${m}`?"))
+}
+
+/// `expr` with its dice indices moved past the first `offset` dice.
+fn shift_dice(expr: &Expr, offset: usize) -> Expr {
+    match expr {
+        Expr::Num(x) => Expr::Num(*x),
+        Expr::Dice(i) => Expr::Dice(i + offset),
+        Expr::Neg(e) => Expr::Neg(shift_dice(e, offset).into()),
+        Expr::Paren(e) => Expr::Paren(shift_dice(e, offset).into()),
+        Expr::BinOp(op, a, b) => Expr::BinOp(
+            *op,
+            shift_dice(a, offset).into(),
+            shift_dice(b, offset).into(),
+        ),
+    }
+}
+
+struct Parser<'m> {
+    toks: Vec<Spanned>,
+    /// Char length of the notation, where an unexpected end points at
+    end: usize,
+    i: usize,
+    dice: Vec<Dice>,
+    macros: &'m Macros,
+}
+
+impl<'m> Parser<'m> {
+    fn new(notation: &str, macros: &'m Macros) -> Result<Self> {
+        Ok(Self {
+            toks: tokenize(notation)?,
+            end: notation.chars().count(),
+            i: 0,
+            dice: vec![],
+            macros,
+        })
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<&Token> {
+        self.toks.get(self.i).map(|t| &t.tok)
+    }
+
+    /// Char position of the next token, or the end of the notation.
+    #[inline]
+    fn pos(&self) -> usize {
+        self.toks.get(self.i).map_or(self.end, |t| t.pos)
+    }
+
+    fn err(pos: usize, kind: ParseErrKind) -> DiceErr {
+        ParseErr::new(pos, kind).into()
+    }
+
+    fn unexpected(&self, t: &Spanned) -> DiceErr {
+        let suggestion = match &t.tok {
+            Token::Ident(s) => suggest(s),
+            _ => None,
+        };
+        ParseErr::new(t.pos, ParseErrKind::UnexpectedToken(t.tok.to_string()))
+            .with_suggestion(suggestion)
+            .into()
+    }
+
+    /// An error for whatever comes next, be it a token or the end.
+    fn unexpected_next(&self) -> DiceErr {
+        match self.toks.get(self.i) {
+            Some(t) => self.unexpected(t),
+            None => Self::err(self.end, ParseErrKind::UnexpectedEnd),
+        }
+    }
+
+    fn expect(&mut self, tok: Token) -> Result<()> {
+        if self.peek() != Some(&tok) {
+            return Err(self.unexpected_next());
+        }
+        self.i += 1;
+        Ok(())
+    }
+
+    fn num(&mut self) -> Result<i64> {
+        let Some(Token::Num(x)) = self.peek() else {
+            return Err(self.unexpected_next());
+        };
+        let x = *x;
+        self.i += 1;
+        Ok(x)
+    }
+
+    fn repetition(&mut self) -> Result<Repeated> {
+        let pos = self.pos();
+
+        let times = match (self.peek(), self.toks.get(self.i + 1).map(|t| &t.tok)) {
+            (Some(Token::Num(n)), Some(Token::Ident(x))) if x.starts_with('x') => {
+                let n = *n;
+                self.i += 1;
+                self.keyword(&["x"]);
+                n
+            }
+            (Some(Token::Ident(r)), _) if r == "repeat" => {
+                self.i += 1;
+                self.expect(Token::LParen)?;
+                let n = self.num()?;
+                self.expect(Token::Comma)?;
+                let notation = self.notation()?;
+                self.expect(Token::RParen)?;
+                return self.finish_repetition(notation, n, pos);
+            }
+            _ => 1,
+        };
+
+        let notation = self.notation()?;
+        self.finish_repetition(notation, times, pos)
+    }
+
+    /// Attach the trailing `# label`, if any, and check the repeat count.
+    fn finish_repetition(
+        &mut self,
+        mut notation: Notation,
+        times: i64,
+        pos: usize,
+    ) -> Result<Repeated> {
+        if let Some(Token::Label(label)) = self.peek() {
+            notation.label = Some(label.clone());
+            self.i += 1;
+        }
+
+        if !(1..=MAX_BATCH_ROWS as i64).contains(&times) {
+            let kind =
+                ParseErrKind::Invalid(format!("repetition must be between 1 and {MAX_BATCH_ROWS}"));
+            return Err(Self::err(pos, kind));
+        }
+        Ok(Repeated {
+            notation,
+            times: times as usize,
+        })
+    }
+
+    /// Parse one expression along with the dice groups it owns.
+    fn notation(&mut self) -> Result<Notation> {
+        let start = self.i;
+        let mut expr = self.expr()?;
+        let lone_macro = match self.toks.get(start) {
+            Some(Spanned {
+                tok: Token::Macro(name),
+                ..
+            }) if self.i == start + 1 => self.macros.get(name),
+            _ => None,
+        };
+        if let (Some(_), Expr::Paren(e)) = (lone_macro, &expr) {
+            expr = (**e).clone();
+        }
+        let target = self.target()?.or(lone_macro.and_then(|m| m.target));
+        Ok(Notation {
+            expr,
+            dice: std::mem::take(&mut self.dice),
+            target,
+            label: lone_macro.and_then(|m| m.label.clone()),
+        })
+    }
+
+    /// Parse an optional target number: `vs 15`, `>= DC 15`, `DC 15`.
+    ///
+    /// `vs` and a bare `DC` mean the total must meet or beat the number.
+    fn target(&mut self) -> Result<Option<Compare>> {
+        let op = if self.keyword(&["vs"]).is_some() {
+            match self.peek() {
+                Some(Token::Cmp(op)) => {
+                    let op = *op;
+                    self.i += 1;
+                    op
+                }
+                _ => CmpOp::Ge,
+            }
+        } else if let Some(Token::Cmp(op)) = self.peek() {
+            let op = *op;
+            self.i += 1;
+            op
+        } else if matches!(self.peek(), Some(Token::Ident(dc)) if dc.starts_with("dc")) {
+            CmpOp::Ge
+        } else {
+            return Ok(None);
+        };
+
+        self.keyword(&["dc"]);
+        let value = self.num()?;
+        Ok(Some(Compare { op, value }))
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => Op::Add,
+                Some(Token::Minus) => Op::Sub,
+                _ => return Ok(lhs),
+            };
+            self.i += 1;
+            let rhs = self.term()?;
+            lhs = Expr::BinOp(op, lhs.into(), rhs.into());
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => Op::Mul,
+                Some(Token::Slash(r)) => Op::Div(*r),
+                Some(Token::Percent) => Op::Rem,
+                _ => return Ok(lhs),
+            };
+            self.i += 1;
+            let rhs = self.unary()?;
+            lhs = Expr::BinOp(op, lhs.into(), rhs.into());
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if let Some(Token::Minus) = self.peek() {
+            self.i += 1;
+            return Ok(Expr::Neg(self.unary()?.into()));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        let pos = self.pos();
+        match self.peek() {
+            Some(Token::Num(x)) => {
+                let x = *x;
+                self.i += 1;
+                match self.keyword(&["df", "d"]) {
+                    Some(d) => self.dice(x, d == "df", pos),
+                    None => Ok(Expr::Num(x)),
+                }
+            }
+            Some(Token::Ident(_)) => match self.keyword(&["df", "d"]) {
+                Some(d) => self.dice(1, d == "df", pos),
+                None => Err(self.unexpected_next()),
+            },
+            Some(Token::LParen) => {
+                self.i += 1;
+                let e = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(Expr::Paren(e.into()))
+            }
+            Some(Token::Macro(name)) => {
+                let Some(m) = self.macros.get(name) else {
+                    let err = ParseErr::new(pos, ParseErrKind::UnknownMacro(name.clone()))
+                        .with_suggestion(suggest_macro(name, self.macros));
+                    return Err(err.into());
+                };
+                self.i += 1;
+                let expr = shift_dice(&m.expr, self.dice.len());
+                self.dice.extend(m.dice.iter().cloned());
+                Ok(match expr {
+                    e @ (Expr::BinOp(..) | Expr::Neg(_)) => Expr::Paren(e.into()),
+                    e => e,
+                })
+            }
+            _ => Err(self.unexpected_next()),
+        }
+    }
+
+    /// Parse the rest of a dice group, after the `d` (or `dF`).
+    fn dice(&mut self, count: i64, fate: bool, pos: usize) -> Result<Expr> {
+        let faces_pos = self.pos();
+        let faces = match self.peek() {
+            _ if fate => Faces::Fate,
+            Some(Token::Num(x)) => Faces::Range(*x),
+            Some(Token::Faces(v)) => match v.iter().map(|f| f.parse()).collect() {
+                Ok(v) => Faces::Custom(v),
+                Err(_) => Faces::Named(v.clone()),
+            },
+            _ => return Err(self.unexpected_next()),
+        };
+        if !fate {
+            self.i += 1;
+        }
+
+        if count <= 0 {
+            return Err(Self::err(pos, ParseErrKind::ZeroCount));
+        }
+        if count as usize > MAX_DICE {
+            let err = ParseErr::new(pos, ParseErrKind::TooManyDice)
+                .with_suggestion(Some(format!("roll `{MAX_DICE}` or fewer dice per group")));
+            return Err(err.into());
+        }
+        if faces.n_faces() <= 0 {
+            return Err(Self::err(faces_pos, ParseErrKind::ZeroFaces));
+        }
+
+        let mut dice = Dice {
+            faces,
+            count: count as usize,
+            keep: None,
+            explode: None,
+            reroll: None,
+            success: None,
+            advantage: None,
+        };
+
+        while let Some(pos) = self.toks.get(self.i).map(|t| t.pos) {
+            if let Some(Token::Bang) = self.peek() {
+                self.i += 1;
+                if dice.explode.is_some() {
+                    return Err(Self::repeated("!", pos));
+                }
+                let kind = if let Some(Token::Bang) = self.peek() {
+                    self.i += 1;
+                    ExplodeKind::Compound
+                } else if self.keyword(&["p"]).is_some() {
+                    ExplodeKind::Penetrate
+                } else {
+                    ExplodeKind::Explode
+                };
+                let on = self.compare()?;
+                let default_on = Compare {
+                    op: CmpOp::Eq,
+                    value: dice.faces.highest(),
+                };
+                if dice.faces.all_match(on.unwrap_or(default_on)) {
+                    let kind =
+                        ParseErrKind::Invalid("explosion would trigger on every face".into());
+                    return Err(Self::err(pos, kind));
+                }
+                dice.explode = Some(Explode { kind, on });
+                continue;
+            }
+
+            if let Some(m) = self.keyword(&["ro", "r"]) {
+                if dice.reroll.is_some() {
+                    return Err(Self::repeated(&m, pos));
+                }
+                let Some(on) = self.compare()? else {
+                    return Err(Self::err(pos, ParseErrKind::MissingArgument(m)));
+                };
+                let once = m == "ro";
+                if !once && dice.faces.all_match(on) {
+                    let err = ParseErr::new(
+                        pos,
+                        ParseErrKind::Invalid("reroll would trigger on every face".into()),
+                    )
+                    .with_suggestion(Some("use `ro` to reroll only once".into()));
+                    return Err(err.into());
+                }
+                dice.reroll = Some(Reroll { once, on });
+                continue;
+            }
+
+            // A comparison right after the dice makes a pool, unless it is
+            // a `DC` which targets the whole expression instead
+            let pool_target = matches!(
+                self.toks.get(self.i + 1).map(|t| &t.tok),
+                Some(Token::Num(_))
+            );
+            if let (Some(Token::Cmp(_)), true) = (self.peek(), pool_target) {
+                if dice.success.is_some() {
+                    return Err(Self::repeated("target", pos));
+                }
+                let on = self.compare()?.expect("comparison operator was peeked");
+                dice.success = Some(Success { on, fail: None });
+                continue;
+            }
+
+            if let Some(success) = dice.success.as_mut().filter(|s| s.fail.is_none()) {
+                if let Some(m) = self.keyword(&["f"]) {
+                    let Some(fail) = self.compare()? else {
+                        return Err(Self::err(pos, ParseErrKind::MissingArgument(m)));
+                    };
+                    success.fail = Some(fail);
+                    continue;
+                }
+            }
+
+            if let Some(m) = self.keyword(&["adv", "dis", "bonus", "b", "penalty", "p"]) {
+                if dice.advantage.is_some() {
+                    return Err(Self::repeated(&m, pos));
+                }
+                dice.advantage = Some(match m.as_str() {
+                    "adv" => Advantage::Adv,
+                    "dis" => Advantage::Dis,
+                    _ => {
+                        let n = match self.peek() {
+                            Some(Token::Num(_)) => self.modifier_arg(&m, pos)?,
+                            _ => 1,
+                        };
+                        if !(1..=MAX_BONUS_DICE).contains(&n) {
+                            let kind = ParseErrKind::Invalid(format!(
+                                "modifier '{m}' takes 1 to {MAX_BONUS_DICE} dice"
+                            ));
+                            return Err(Self::err(pos, kind));
+                        }
+                        if m.starts_with('b') {
+                            Advantage::Bonus(n)
+                        } else {
+                            Advantage::Penalty(n)
+                        }
+                    }
+                });
+                continue;
+            }
+
+            let Some(m) = self.keyword(&["kh", "kl", "dh", "dl", "k"]) else {
+                break;
+            };
+            if dice.keep.is_some() {
+                return Err(Self::repeated(&m, pos));
+            }
+            let n = self.modifier_arg(&m, pos)?;
+            dice.keep = Some(match m.as_str() {
+                "k" | "kh" => KeepDrop::KeepHighest(n),
+                "kl" => KeepDrop::KeepLowest(n),
+                "dh" => KeepDrop::DropHighest(n),
+                _ => KeepDrop::DropLowest(n),
+            });
+        }
+
+        if let Some(t) = self.toks.get(self.i) {
+            if let Token::Ident(m) = &t.tok {
+                // `vs`/`dc` start the target of the whole expression
+                if !m.starts_with("vs") && !m.starts_with("dc") {
+                    let err = ParseErr::new(t.pos, ParseErrKind::UnknownModifier(m.clone()))
+                        .with_suggestion(suggest(m));
+                    return Err(err.into());
+                }
+            }
+        }
+
+        let modified = dice.keep.is_some()
+            || dice.explode.is_some()
+            || dice.reroll.is_some()
+            || dice.success.is_some();
+        if matches!(dice.faces, Faces::Named(_)) && (modified || dice.advantage.is_some()) {
+            let kind = ParseErrKind::Invalid("dice with named faces cannot take modifiers".into());
+            return Err(Self::err(pos, kind));
+        }
+        match dice.advantage {
+            Some(Advantage::Adv | Advantage::Dis) if dice.count != 1 || dice.keep.is_some() => {
+                let err = ParseErr::new(
+                    pos,
+                    ParseErrKind::Invalid("advantage needs a single die without keep/drop".into()),
+                )
+                .with_suggestion(Some(format!("try `1d{}adv`", dice.faces)));
+                return Err(err.into());
+            }
+            Some(Advantage::Bonus(_) | Advantage::Penalty(_))
+                if dice.count != 1 || dice.faces != Faces::Range(100) || modified =>
+            {
+                let err = ParseErr::new(
+                    pos,
+                    ParseErrKind::Invalid("bonus/penalty dice need a plain 1d100".into()),
+                )
+                .with_suggestion(Some("try `1d100b1` or `1d100p1`".into()));
+                return Err(err.into());
+            }
+            _ => {}
+        }
+
+        self.dice.push(dice);
+        Ok(Expr::Dice(self.dice.len() - 1))
+    }
+
+    /// Consume the longest of `keywords` that prefixes the next identifier.
+    ///
+    /// Modifiers are written without separators (`4d6!pkh3`), so the lexer
+    /// hands us one identifier and we split it here, leaving the remainder
+    /// as the next token.
+    fn keyword(&mut self, keywords: &[&str]) -> Option<String> {
+        let t = self.toks.get_mut(self.i)?;
+        let Token::Ident(ident) = &mut t.tok else {
+            return None;
+        };
+        let kw = keywords
+            .iter()
+            .filter(|kw| ident.starts_with(*kw))
+            .max_by_key(|kw| kw.len())?;
+
+        if ident.len() == kw.len() {
+            self.i += 1;
+        } else {
+            *ident = ident[kw.len()..].to_string();
+            t.pos += kw.len();
+        }
+        Some(kw.to_string())
+    }
+
+    /// Parse an optional comparison such as `>=5`; a bare number means `=`.
+    fn compare(&mut self) -> Result<Option<Compare>> {
+        let op = match self.peek() {
+            Some(Token::Cmp(op)) => {
+                let op = *op;
+                self.i += 1;
+                op
+            }
+            Some(Token::Num(_)) => CmpOp::Eq,
+            _ => return Ok(None),
+        };
+        let value = self.num()?;
+        Ok(Some(Compare { op, value }))
+    }
+
+    /// Parse the count following a modifier such as `kh`.
+    fn modifier_arg(&mut self, m: &str, pos: usize) -> Result<usize> {
+        match self.peek() {
+            Some(Token::Num(x)) => {
+                let x = *x as usize;
+                self.i += 1;
+                Ok(x)
+            }
+            next => {
+                // `kx3` lexes as `k` followed by `x`, likely a typo of `kh`
+                let suggestion = match next {
+                    Some(Token::Ident(rest)) => suggest(&format!("{m}{rest}")),
+                    _ => None,
+                };
+                let err = ParseErr::new(pos, ParseErrKind::MissingArgument(m.into()))
+                    .with_suggestion(suggestion);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn repeated(m: &str, pos: usize) -> DiceErr {
+        Self::err(pos, ParseErrKind::RepeatedModifier(m.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_batch, parse_labelled, Dice, Expr, Macros, Notation, Op};
+    use crate::dice::{
+        Advantage, CmpOp, Compare, DiceErr, ExplodeKind, Faces, KeepDrop, ParseErrKind, Rounding,
+    };
+
+    #[test]
+    fn test_parse_implicit_count() {
+        let n = parse("d20").unwrap();
+        assert_eq!(n.expr, Expr::Dice(0));
+        assert_eq!(
+            n.dice,
+            vec![Dice {
+                faces: Faces::Range(20),
+                count: 1,
+                keep: None,
+                explode: None,
+                reroll: None,
+                success: None,
+                advantage: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_keep_drop() {
+        let n = parse("4d6kh3 + 2d20kl1 + 10d6dl2 + 3d8DH1").unwrap();
+        let keeps: Vec<_> = n.dice.iter().map(|d| d.keep).collect();
+        assert_eq!(
+            keeps,
+            vec![
+                Some(KeepDrop::KeepHighest(3)),
+                Some(KeepDrop::KeepLowest(1)),
+                Some(KeepDrop::DropLowest(2)),
+                Some(KeepDrop::DropHighest(1)),
+            ]
+        );
+        assert_eq!(n.to_string(), "4d6kh3 + 2d20kl1 + 10d6dl2 + 3d8dh1");
+
+        assert!(parse("4d6kh").is_err());
+        assert!(parse("4d6kh3kl1").is_err());
+    }
+
+    #[test]
+    fn test_parse_explode() {
+        let n = parse("1d6! + 1d6!! + 1d6!p + 1d10!>=8 + 1d10!!9kh1").unwrap();
+        let explodes: Vec<_> = n.dice.iter().map(|d| d.explode.unwrap()).collect();
+        assert_eq!(explodes[0].kind, ExplodeKind::Explode);
+        assert_eq!(explodes[1].kind, ExplodeKind::Compound);
+        assert_eq!(explodes[2].kind, ExplodeKind::Penetrate);
+        assert_eq!(
+            explodes[3].on,
+            Some(Compare {
+                op: CmpOp::Ge,
+                value: 8
+            })
+        );
+        assert_eq!(n.dice[4].keep, Some(KeepDrop::KeepHighest(1)));
+        assert_eq!(
+            n.to_string(),
+            "1d6! + 1d6!! + 1d6!p + 1d10!>=8 + 1d10!!=9kh1"
+        );
+
+        assert_eq!(
+            parse("4d6!pkh3").unwrap().dice[0].keep,
+            Some(KeepDrop::KeepHighest(3))
+        );
+        assert!(parse("1d6!>=1").is_err());
+        assert!(parse("1d1!").is_err());
+        assert!(parse("1d6!!!").is_err());
+    }
+
+    #[test]
+    fn test_parse_reroll() {
+        let n = parse("2d6r1 + 1d20ro<3 + 4d6r<=2kh3").unwrap();
+        let rerolls: Vec<_> = n.dice.iter().map(|d| d.reroll.unwrap()).collect();
+        assert!(!rerolls[0].once);
+        assert!(rerolls[1].once);
+        assert_eq!(
+            rerolls[2].on,
+            Compare {
+                op: CmpOp::Le,
+                value: 2
+            }
+        );
+        assert_eq!(n.dice[2].keep, Some(KeepDrop::KeepHighest(3)));
+        assert_eq!(n.to_string(), "2d6r=1 + 1d20ro<3 + 4d6r<=2kh3");
+
+        assert!(parse("1d6r").is_err());
+        assert!(parse("1d6r<7").is_err());
+        assert!(parse("1d6ro<7").is_ok());
+    }
+
+    #[test]
+    fn test_parse_success() {
+        let n = parse("8d10>=7 + 6d6>=5f1 + 10d10>=8!").unwrap();
+        let s: Vec<_> = n.dice.iter().map(|d| d.success.unwrap()).collect();
+        assert_eq!(
+            s[0].on,
+            Compare {
+                op: CmpOp::Ge,
+                value: 7
+            }
+        );
+        assert_eq!(s[0].fail, None);
+        assert_eq!(
+            s[1].fail,
+            Some(Compare {
+                op: CmpOp::Eq,
+                value: 1
+            })
+        );
+        assert!(n.dice[2].explode.is_some());
+        assert_eq!(n.to_string(), "8d10>=7 + 6d6>=5f=1 + 10d10!>=8");
+
+        assert!(parse("6d6f1").is_err());
+        assert!(parse("6d6>=5f").is_err());
+        assert!(parse("6d6>=5>=4").is_err());
+    }
+
+    #[test]
+    fn test_parse_faces() {
+        let n = parse("4dF + 2df! + d{1,1,2,3,5,8} + 1d{-1, 0,1} + d{Hit,miss,crit}").unwrap();
+        let faces: Vec<_> = n.dice.iter().map(|d| d.faces.clone()).collect();
+        assert_eq!(
+            faces,
+            vec![
+                Faces::Fate,
+                Faces::Fate,
+                Faces::Custom(vec![1, 1, 2, 3, 5, 8]),
+                Faces::Custom(vec![-1, 0, 1]),
+                Faces::Named(vec!["Hit".into(), "miss".into(), "crit".into()]),
+            ]
+        );
+        assert_eq!(
+            n.to_string(),
+            "4dF + 2dF! + 1d{1,1,2,3,5,8} + 1d{-1,0,1} + 1d{Hit,miss,crit}"
+        );
+        assert_eq!(parse("4dFkh2").unwrap().dice[0].faces, Faces::Fate);
+
+        assert!(parse("1d{}").is_err());
+        assert!(parse("1d{1,,2}").is_err());
+        assert!(parse("1d{1,2").is_err());
+        assert!(parse("2d{hit,miss}kh1").is_err());
+        assert!(parse("1d{5,5}!").is_err());
+    }
+
+    #[test]
+    fn test_parse_advantage() {
+        let n = parse("d20adv + 1d20DIS + d100b + 1d100penalty2").unwrap();
+        let adv: Vec<_> = n.dice.iter().map(|d| d.advantage.unwrap()).collect();
+        assert_eq!(
+            adv,
+            vec![
+                Advantage::Adv,
+                Advantage::Dis,
+                Advantage::Bonus(1),
+                Advantage::Penalty(2),
+            ]
+        );
+        assert_eq!(n.to_string(), "1d20adv + 1d20dis + 1d100b1 + 1d100p2");
+        assert!(parse("1d20adv>=15").is_ok());
+        assert!(parse("1d6!p").unwrap().dice[0].advantage.is_none());
+
+        assert!(parse("2d20adv").is_err());
+        assert!(parse("1d20advkh1").is_err());
+        assert!(parse("1d20advdis").is_err());
+        assert!(parse("1d20b1").is_err());
+        assert!(parse("1d100b1r1").is_err());
+        assert!(parse("1d100p0").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let b = parse_batch(
+            "6x 4d6kh3; repeat(3, 1d20+5); 1d20+7; 2d6+4;",
+            &Macros::new(),
+        )
+        .unwrap();
+        let rows: Vec<_> = b
+            .iter()
+            .map(|r| (r.notation.to_string(), r.times))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("4d6kh3".into(), 6),
+                ("1d20 + 5".into(), 3),
+                ("1d20 + 7".into(), 1),
+                ("2d6 + 4".into(), 1),
+            ]
+        );
+        // Every expression owns its dice groups
+        assert_eq!(b[3].notation.dice.len(), 1);
+        assert_eq!(parse_batch("2xd20", &Macros::new()).unwrap()[0].times, 2);
+
+        assert!(parse_batch("0x 1d6", &Macros::new()).is_err());
+        assert!(parse_batch("21x 1d6", &Macros::new()).is_err());
+        assert!(parse_batch("repeat(3 1d6)", &Macros::new()).is_err());
+        assert!(parse_batch("1d6;;1d6", &Macros::new()).is_err());
+        assert!(parse("1d6; 1d6").is_err());
+    }
+
+    #[test]
+    fn test_parse_label_target() {
+        let b = parse_batch(
+            "1d20+5 # Perception; 1d20+7 vs 15; 1d20 >= DC 15 #Stealth check",
+            &Macros::new(),
+        )
+        .unwrap();
+        assert_eq!(b[0].notation.label.as_deref(), Some("Perception"));
+        assert_eq!(b[0].notation.target, None);
+
+        let ge_15 = Some(Compare {
+            op: CmpOp::Ge,
+            value: 15,
+        });
+        assert_eq!(b[1].notation.target, ge_15);
+        assert_eq!(b[1].notation.to_string(), "1d20 + 7 >= DC 15");
+        assert_eq!(b[2].notation.target, ge_15);
+        assert_eq!(b[2].notation.dice[0].success, None);
+        assert_eq!(b[2].notation.label.as_deref(), Some("Stealth check"));
+
+        let b = parse_batch(
+            "repeat(2, 1d100 vs <= 45) # Spot Hidden; 3x d20 dc 12",
+            &Macros::new(),
+        )
+        .unwrap();
+        assert_eq!(b[0].notation.label.as_deref(), Some("Spot Hidden"));
+        assert_eq!(
+            b[0].notation.target,
+            Some(Compare {
+                op: CmpOp::Le,
+                value: 45
+            })
+        );
+        assert_eq!(b[1].notation.target.unwrap().value, 12);
+
+        // Right after the dice, a comparison still makes a pool
+        assert!(parse("1d20>=15").unwrap().dice[0].success.is_some());
+        assert!(parse_batch("1d20 vs", &Macros::new()).is_err());
+        assert!(parse_batch("1d20 #", &Macros::new()).is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        // Logged rolls are stored as displayed and parsed again to verify them
+        for s in [
+            "4d6!pkh3 + 2",
+            "8d10r<2!!>=9 - 1d4",
+            "6d10>=8f1",
+            "1d20adv + 5 >= DC 15",
+            "1d100b2 vs <= 45",
+            "4dF + d{1,1,2,3} + 2d{hit,miss}",
+            "-(2d6 + 3) * 2 /^ 3 /~ 2 % 5",
+            "3d8ro=1dl1 / 2",
+        ] {
+            let n = parse(s).unwrap();
+            assert_eq!(parse(&n.to_string()).unwrap(), n, "{s} displayed as {n}");
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| match parse(s) {
+            Err(DiceErr::ParseErr(e)) => e,
+            r => panic!("expected a parse error for {s}, got {r:?}"),
+        };
+
+        let e = err("1d20kx3");
+        assert_eq!(e.pos, 4);
+        assert_eq!(e.kind, ParseErrKind::MissingArgument("k".into()));
+        assert_eq!(e.suggestion.as_deref(), Some("did you mean `kh`?"));
+
+        let e = err("4d6!pkj3");
+        assert_eq!(e.pos, 5);
+        assert_eq!(e.suggestion.as_deref(), Some("did you mean `kh`?"));
+
+        let e = err("4d6zz");
+        assert_eq!(e.pos, 3);
+        assert_eq!(e.kind, ParseErrKind::UnknownModifier("zz".into()));
+
+        let e = err("1d20 + ");
+        assert_eq!(e.pos, 7);
+        assert_eq!(e.kind, ParseErrKind::UnexpectedEnd);
+
+        let e = err("2 * (1d6 + 3");
+        assert_eq!(e.pos, 12);
+
+        assert_eq!(err("1d0").kind, ParseErrKind::ZeroFaces);
+        assert_eq!(err("1d0").pos, 2);
+        assert_eq!(err("0d6").kind, ParseErrKind::ZeroCount);
+        assert_eq!(err("201d6").kind, ParseErrKind::TooManyDice);
+        assert_eq!(err("1d6 + ?").kind, ParseErrKind::UnexpectedChar('?'));
+        assert_eq!(
+            err("1d20 + advantage").suggestion,
+            None,
+            "too far from any keyword"
+        );
+        assert_eq!(
+            err("1d20 + pnealty").suggestion.as_deref(),
+            Some("did you mean `penalty`?")
+        );
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let n = parse("2*(1d6+3) - 1d8/2").unwrap();
+        assert_eq!(n.dice.len(), 2);
+        assert_eq!(n.to_string(), "2 * (1d6 + 3) - 1d8 / 2");
+
+        let Expr::BinOp(Op::Sub, lhs, rhs) = n.expr else {
+            panic!("expected subtraction at the root");
+        };
+        assert!(matches!(*lhs, Expr::BinOp(Op::Mul, _, _)));
+        assert!(matches!(*rhs, Expr::BinOp(Op::Div(Rounding::Down), _, _)));
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        let n = parse("1d20 - -(2d4)").unwrap();
+        assert_eq!(n.to_string(), "1d20 - -(2d4)");
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        assert!(parse("").is_err());
+        assert!(parse("1d").is_err());
+        assert!(parse("0d6").is_err());
+        assert!(parse("1d0").is_err());
+        assert!(parse("(1d6").is_err());
+        assert!(parse("1d6)").is_err());
+        assert!(parse("1d6 + foo").is_err());
+        assert!(parse("1d6xy2").is_err());
+    }
+
+    #[test]
+    fn test_with_advantage() {
+        let n = parse("1d20dis + 1d20 + 1d6 + 2d20kh1").unwrap();
+        assert_eq!(
+            n.with_advantage().unwrap().to_string(),
+            "1d20adv + 1d20adv + 1d6 + 2d20kh1"
+        );
+        assert_eq!(parse("1d20adv + 5").unwrap().with_advantage(), None);
+        assert_eq!(parse("3d6").unwrap().with_advantage(), None);
+    }
+
+    #[test]
+    fn test_parse_macros() {
+        let macros = Macros::from([
+            (
+                "sword".into(),
+                parse_labelled("1d20+11 # Longsword").unwrap(),
+            ),
+            ("dmg".into(), parse("1d8+5").unwrap()),
+            ("save".into(), parse("1d20 vs 15").unwrap()),
+        ]);
+        let one = |s: &str| {
+            let mut batch = parse_batch(s, &macros).unwrap();
+            assert_eq!(batch.len(), 1);
+            batch.remove(0).notation
+        };
+
+        let n = one("$sword");
+        assert_eq!(n.to_string(), "1d20 + 11");
+        assert_eq!(n.label.as_deref(), Some("Longsword"));
+
+        let n = one("$Sword + 2 # Bless");
+        assert_eq!(n.to_string(), "(1d20 + 11) + 2");
+        assert_eq!(n.label.as_deref(), Some("Bless"));
+
+        let n = one("2 * $dmg - 1d4");
+        assert_eq!(n.to_string(), "2 * (1d8 + 5) - 1d4");
+        assert_eq!(n.dice.len(), 2);
+        assert_eq!(
+            parse(&n.to_string()).unwrap(),
+            Notation { label: None, ..n }
+        );
+
+        let n = one("1d4 + $dmg");
+        assert_eq!(n.dice[1].to_string(), "1d8");
+        assert_eq!(one("$save").target, parse("1d20 vs 15").unwrap().target);
+        assert_eq!(one("$save vs 20").target.unwrap().value, 20);
+
+        let e = match parse_batch("1d20 + $swrod", &macros) {
+            Err(DiceErr::ParseErr(e)) => e,
+            other => panic!("expected a parse error, got {other:?}"),
+        };
+        assert_eq!(e.pos, 7);
+        assert_eq!(e.kind, ParseErrKind::UnknownMacro("swrod".into()));
+        assert_eq!(e.suggestion.as_deref(), Some("did you mean `$sword`?"));
+        assert!(parse("$").is_err());
+    }
+}
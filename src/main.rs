@@ -1,4 +1,5 @@
 mod commands;
+mod dice;
 mod repo;
 
 use std::{str::FromStr, sync::Arc, time::Duration};
@@ -9,7 +10,14 @@ use figment::{
     Figment,
 };
 use lavalink_rs::node::NodeBuilder;
-use repo::{music::MusicRepo, nist_beacon::NistBeaconRepo};
+use repo::{
+    beacon::{Beacon, Provider},
+    music::MusicRepo,
+    nist_beacon::NistBeaconRepo,
+    random::{RandomSources, SourceKind},
+    roll::RollRepo,
+    roll_macro::MacroRepo,
+};
 use sea_orm::{ConnectOptions, Database};
 use serde::Deserialize;
 use songbird::SerenityInit;
@@ -31,9 +39,33 @@ struct Config {
     log_level: String,
 
     guild_ids: Vec<u64>,
+    /// Read every message for `[[1d20]]` inline rolls, a privileged intent
+    /// that must also be enabled for the bot in the developer portal
+    #[serde(default)]
+    message_content_intent: bool,
+    /// Where rolls draw their bits from, unless a guild picks another
+    #[serde(default)]
+    random_source: SourceKind,
+    /// Seed of the `seeded` random source
+    #[serde(default)]
+    random_seed: u64,
+    /// Beacon pulses to fetch ahead of time, 512 bits each
+    #[serde(default = "default_beacon_reserve")]
+    beacon_reserve: usize,
+    /// Beacons to fetch pulses from, the next one used while one is down
+    #[serde(default = "default_beacons")]
+    beacons: Vec<Beacon>,
     lavalink_nodes: Vec<LavalinkNodeConfig>,
 }
 
+fn default_beacon_reserve() -> usize {
+    4
+}
+
+fn default_beacons() -> Vec<Beacon> {
+    vec![Beacon::new(Provider::Nist)]
+}
+
 #[derive(Debug, Deserialize)]
 struct LavalinkNodeConfig {
     host: String,
@@ -84,10 +116,21 @@ async fn main() -> Result<(), Box<dyn Send + Sync + std::error::Error>> {
 
     db.ping().await?;
 
-    let nist_repo: Arc<NistBeaconRepo> = Arc::new(NistBeaconRepo::new(db));
+    let nist_repo: Arc<NistBeaconRepo> = Arc::new(NistBeaconRepo::new(db.clone(), conf.beacons));
+    nist_repo.clone().spawn_prefetcher(conf.beacon_reserve);
+    let roll_repo = Arc::new(RollRepo::new(db.clone()));
+    let macro_repo = Arc::new(MacroRepo::new(db));
+    let random = Arc::new(RandomSources::new(
+        conf.random_source,
+        nist_repo.clone(),
+        conf.random_seed,
+    ));
 
     let token = &conf.discord_token;
-    let intents = serenity::GatewayIntents::non_privileged();
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    if conf.message_content_intent {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
@@ -96,7 +139,17 @@ async fn main() -> Result<(), Box<dyn Send + Sync + std::error::Error>> {
                 commands::pingmusic(),
                 commands::stop(),
                 commands::roll(),
+                commands::rollsettings(),
+                commands::history(),
+                commands::verify(),
+                commands::odds(),
+                commands::roll_macro(),
+                commands::rollsource(),
+                commands::beacon(),
             ],
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(commands::event_handler(ctx, event, framework, data))
+            },
             ..Default::default()
         })
         .setup({
@@ -118,7 +171,9 @@ async fn main() -> Result<(), Box<dyn Send + Sync + std::error::Error>> {
                         .map(|n| n.into_node_builder(ready.application.id))
                         .collect();
                     let music_repo = Arc::new(MusicRepo::new(lavalink_nodes).await);
-                    Ok(Data::new(nist_repo, music_repo))
+                    Ok(Data::new(
+                        nist_repo, music_repo, roll_repo, macro_repo, random,
+                    ))
                 })
             }
         })
@@ -0,0 +1,650 @@
+use entity::nist_rand_entry;
+use openssl::{hash::MessageDigest, sign::Verifier, x509::X509};
+use sea_orm::ActiveValue;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_hex::{SerHex, StrictCap};
+use sha2::{Digest, Sha256, Sha512};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::nist_beacon::{NistBeaconRepoErr, PulseId, Result, N_BYTES};
+
+/// The drand chain is identified by its hash rather than an index
+const DRAND_CHAIN: i32 = 1;
+
+/// A public randomness beacon.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// NIST's Randomness Beacon 2.0
+    #[default]
+    #[name = "NIST"]
+    Nist,
+    /// The beacon of Universidad de Chile, in the NIST format
+    #[name = "UChile"]
+    Chile,
+    /// The beacon of Inmetro in Brazil, in the NIST format
+    #[name = "Inmetro"]
+    Brazil,
+    /// The League of Entropy's drand
+    #[name = "drand"]
+    Drand,
+}
+
+impl Provider {
+    /// Stable name stored in the database.
+    pub fn key(self) -> &'static str {
+        match self {
+            Provider::Nist => "nist",
+            Provider::Chile => "chile",
+            Provider::Brazil => "brazil",
+            Provider::Drand => "drand",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Nist => "NIST",
+            Provider::Chile => "UChile",
+            Provider::Brazil => "Inmetro",
+            Provider::Drand => "drand",
+        }
+    }
+
+    pub fn is_nist(&self) -> bool {
+        *self == Provider::Nist
+    }
+
+    /// Where the latest pulse is published.
+    fn default_url(self) -> &'static str {
+        match self {
+            Provider::Nist => "https://beacon.nist.gov/beacon/2.0/pulse/last",
+            Provider::Chile => "https://random.uchile.cl/beacon/2.0/pulse/last",
+            Provider::Brazil => "https://beacon.inmetro.gov.br/beacon/2.1/pulse/last",
+            Provider::Drand => "https://api.drand.sh/public/latest",
+        }
+    }
+}
+
+/// A beacon to fetch pulses from, as listed in `Config`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Beacon {
+    pub provider: Provider,
+    /// Overrides the provider's URL of its latest pulse
+    #[serde(default)]
+    url: Option<String>,
+}
+
+impl Beacon {
+    pub fn new(provider: Provider) -> Self {
+        Self {
+            provider,
+            url: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or(self.provider.default_url())
+    }
+
+    /// Fetch the PEM certificate a NIST-format pulse names, from the same
+    /// beacon as the pulse. It is only trusted once
+    /// [`NistBeaconPulse::verify_signature`] has checked it against the id.
+    pub async fn certificate(&self, client: &reqwest::Client, id: &[u8]) -> Result<Vec<u8>> {
+        let base = match self.url().rsplit_once("/pulse/") {
+            Some((base, _)) => base,
+            None => self.url(),
+        };
+        let id: String = id.iter().map(|b| format!("{b:02x}")).collect();
+        let res = client
+            .get(format!("{base}/certificate/{id}"))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// Fetch the latest pulse, unverified.
+    pub async fn latest(&self, client: &reqwest::Client) -> Result<Pulse> {
+        let res = client.get(self.url()).send().await?.error_for_status()?;
+        Ok(match self.provider {
+            Provider::Drand => {
+                let pulse = res.json::<DrandPulse>().await?;
+                let uri = format!("{}/{}", self.url().trim_end_matches("/latest"), pulse.round);
+                Pulse::Drand {
+                    uri,
+                    fetched_at: OffsetDateTime::now_utc(),
+                    pulse,
+                }
+            }
+            provider => Pulse::Nist {
+                provider,
+                pulse: Box::new(res.json::<NistBeaconResponse>().await?.pulse),
+            },
+        })
+    }
+}
+
+/// A pulse of any provider, to be verified and stored.
+#[derive(Debug)]
+pub enum Pulse {
+    Nist {
+        provider: Provider,
+        pulse: Box<NistBeaconPulse>,
+    },
+    Drand {
+        uri: String,
+        /// drand rounds carry no time, only their index
+        fetched_at: OffsetDateTime,
+        pulse: DrandPulse,
+    },
+}
+
+impl Pulse {
+    pub fn id(&self) -> PulseId {
+        match self {
+            Pulse::Nist { provider, pulse } => PulseId {
+                provider: *provider,
+                chain_index: pulse.chain_index,
+                pulse_index: pulse.pulse_index,
+            },
+            Pulse::Drand { pulse, .. } => PulseId {
+                provider: Provider::Drand,
+                chain_index: DRAND_CHAIN,
+                pulse_index: pulse.round,
+            },
+        }
+    }
+
+    pub fn time_stamp(&self) -> Result<OffsetDateTime> {
+        match self {
+            Pulse::Nist { pulse, .. } => Ok(OffsetDateTime::parse(&pulse.time_stamp, &Rfc3339)?),
+            Pulse::Drand { fetched_at, .. } => Ok(*fetched_at),
+        }
+    }
+
+    /// The bits rolls draw from.
+    ///
+    /// A drand round only has 256 bits of randomness, which are hashed with
+    /// SHA-512 to fill a pulse.
+    pub fn output_value(&self) -> [u8; N_BYTES] {
+        match self {
+            Pulse::Nist { pulse, .. } => pulse.output_value,
+            Pulse::Drand { pulse, .. } => Sha512::digest(&pulse.randomness).into(),
+        }
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        match self {
+            Pulse::Nist { pulse, .. } => pulse.verify_output(),
+            Pulse::Drand { pulse, .. } => pulse.verify_randomness(),
+        }
+    }
+
+    /// Check that this pulse follows `prev`, the one before it in its chain.
+    pub fn verify_link(&self, prev: &nist_rand_entry::Model) -> Result<()> {
+        match self {
+            Pulse::Nist { pulse, .. } => pulse.verify_link(prev),
+            Pulse::Drand { pulse, .. } => pulse.verify_link(prev),
+        }
+    }
+
+    /// The row to store the pulse as.
+    pub fn into_entry(self) -> Result<nist_rand_entry::ActiveModel> {
+        let id = self.id();
+        let output_value = self.output_value().to_vec();
+        let entry = nist_rand_entry::ActiveModel {
+            provider: ActiveValue::set(id.provider.key().into()),
+            chain_index: ActiveValue::set(id.chain_index),
+            pulse_index: ActiveValue::set(id.pulse_index),
+            output_value: ActiveValue::set(output_value),
+            ..Default::default()
+        };
+        Ok(match self {
+            Pulse::Nist { pulse, .. } => {
+                let list_value =
+                    |kind| ActiveValue::set(pulse.list_value(kind).map(<[u8]>::to_vec));
+                nist_rand_entry::ActiveModel {
+                    // As published, so that the stored pulse can be hashed again
+                    timestamp: ActiveValue::set(pulse.time_stamp.clone()),
+                    uri: ActiveValue::set(pulse.uri.clone()),
+                    version: ActiveValue::set(Some(pulse.version.clone())),
+                    cipher_suite: ActiveValue::set(Some(pulse.cipher_suite as i32)),
+                    period: ActiveValue::set(Some(pulse.period as i32)),
+                    certificate_id: ActiveValue::set(Some(pulse.certificate_id.clone())),
+                    local_random_value: ActiveValue::set(Some(pulse.local_random_value.clone())),
+                    external_source_id: ActiveValue::set(Some(pulse.external.source_id.clone())),
+                    external_status_code: ActiveValue::set(Some(pulse.external.status_code as i32)),
+                    external_value: ActiveValue::set(Some(pulse.external.value.clone())),
+                    previous_value: list_value("previous"),
+                    hour_value: list_value("hour"),
+                    day_value: list_value("day"),
+                    month_value: list_value("month"),
+                    year_value: list_value("year"),
+                    precommitment_value: ActiveValue::set(Some(pulse.precommitment_value.clone())),
+                    status_code: ActiveValue::set(Some(pulse.status_code as i32)),
+                    signature_value: ActiveValue::set(Some(pulse.signature_value.clone())),
+                    ..entry
+                }
+            }
+            Pulse::Drand {
+                uri,
+                fetched_at,
+                pulse,
+            } => nist_rand_entry::ActiveModel {
+                timestamp: ActiveValue::set(fetched_at.format(&Rfc3339)?),
+                uri: ActiveValue::set(uri),
+                previous_value: ActiveValue::set(pulse.previous_signature),
+                signature_value: ActiveValue::set(Some(pulse.signature)),
+                ..entry
+            },
+        })
+    }
+}
+
+/// A round of drand, its byte fields decoded from hex.
+#[derive(Debug, Deserialize)]
+pub struct DrandPulse {
+    pub round: i64,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub randomness: Vec<u8>,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub signature: Vec<u8>,
+    /// Only set by chained networks
+    #[serde(default, deserialize_with = "hex_bytes_opt")]
+    pub previous_signature: Option<Vec<u8>>,
+}
+
+impl DrandPulse {
+    fn invalid(&self, reason: String) -> NistBeaconRepoErr {
+        NistBeaconRepoErr::InvalidPulse(format!("drand round {}: {reason}", self.round))
+    }
+
+    /// Check that the randomness is the hash of the signature.
+    ///
+    /// The BLS signature itself is not checked.
+    pub fn verify_randomness(&self) -> Result<()> {
+        if Sha256::digest(&self.signature).as_slice() != self.randomness {
+            return Err(self.invalid("randomness is not the hash of the signature".into()));
+        }
+        Ok(())
+    }
+
+    /// Check that this round signs over `prev`'s signature, on chained
+    /// networks.
+    pub fn verify_link(&self, prev: &nist_rand_entry::Model) -> Result<()> {
+        match &self.previous_signature {
+            Some(previous) if prev.signature_value.as_ref() != Some(previous) => {
+                Err(self.invalid(format!(
+                    "previous signature does not match stored round {}",
+                    prev.pulse_index
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn hex_bytes_opt<'de, D: Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<Option<Vec<u8>>, D::Error> {
+    hex_bytes(d).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct NistBeaconResponse {
+    pub pulse: NistBeaconPulse,
+}
+
+/// A pulse of the Beacon 2.0 format, its byte fields decoded from hex.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NistBeaconPulse {
+    pub uri: String,
+    pub version: String,
+    pub cipher_suite: u32,
+    pub period: u32,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub certificate_id: Vec<u8>,
+    pub chain_index: i32,
+    pub pulse_index: i64,
+    /// As published, since the output value is hashed over this string
+    pub time_stamp: String,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub local_random_value: Vec<u8>,
+    pub external: NistBeaconExternal,
+    pub list_values: Vec<NistBeaconListValue>,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub precommitment_value: Vec<u8>,
+    pub status_code: u32,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub signature_value: Vec<u8>,
+    #[serde(with = "SerHex::<StrictCap>")]
+    pub output_value: [u8; N_BYTES],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NistBeaconExternal {
+    #[serde(deserialize_with = "hex_bytes")]
+    pub source_id: Vec<u8>,
+    pub status_code: u32,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+/// The output value of an earlier pulse: the previous one, or the first
+/// of the current hour, day, month or year.
+#[derive(Debug, Deserialize)]
+pub struct NistBeaconListValue {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(deserialize_with = "hex_bytes")]
+    pub value: Vec<u8>,
+}
+
+fn hex_bytes<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<u8>, D::Error> {
+    let hex = String::deserialize(d)?;
+    if hex.len() % 2 != 0 {
+        return Err(serde::de::Error::custom("odd number of hex digits"));
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digit = |b: u8| (b as char).to_digit(16);
+            match (digit(pair[0]), digit(pair[1])) {
+                (Some(hi), Some(lo)) => Ok((hi * 16 + lo) as u8),
+                _ => Err(serde::de::Error::custom("invalid hex digit")),
+            }
+        })
+        .collect()
+}
+
+/// A byte field of a serialized pulse, after its length.
+fn bytes(buf: &mut Vec<u8>, b: &[u8]) {
+    buf.extend((b.len() as u32).to_be_bytes());
+    buf.extend(b);
+}
+
+/// The order of the list values in a serialized pulse
+const LIST_VALUES: [&str; 5] = ["previous", "hour", "day", "month", "year"];
+
+impl NistBeaconPulse {
+    pub fn list_value(&self, kind: &str) -> Option<&[u8]> {
+        self.list_values
+            .iter()
+            .find(|v| v.kind == kind)
+            .map(|v| v.value.as_slice())
+    }
+
+    fn invalid(&self, reason: String) -> NistBeaconRepoErr {
+        NistBeaconRepoErr::InvalidPulse(format!(
+            "pulse {}/{}: {reason}",
+            self.chain_index, self.pulse_index
+        ))
+    }
+
+    /// The fields before the signature, serialized as the Beacon 2.0
+    /// specification does to sign them: integers big-endian, strings and
+    /// byte fields after their length as a `u32`.
+    fn signed_fields(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![];
+        bytes(&mut buf, self.uri.as_bytes());
+        bytes(&mut buf, self.version.as_bytes());
+        buf.extend(self.cipher_suite.to_be_bytes());
+        buf.extend(self.period.to_be_bytes());
+        bytes(&mut buf, &self.certificate_id);
+        buf.extend((self.chain_index as u64).to_be_bytes());
+        buf.extend((self.pulse_index as u64).to_be_bytes());
+        bytes(&mut buf, self.time_stamp.as_bytes());
+        bytes(&mut buf, &self.local_random_value);
+        bytes(&mut buf, &self.external.source_id);
+        buf.extend(self.external.status_code.to_be_bytes());
+        bytes(&mut buf, &self.external.value);
+        for kind in LIST_VALUES {
+            let value = self
+                .list_value(kind)
+                .ok_or_else(|| self.invalid(format!("no {kind} value")))?;
+            bytes(&mut buf, value);
+        }
+        bytes(&mut buf, &self.precommitment_value);
+        buf.extend(self.status_code.to_be_bytes());
+        Ok(buf)
+    }
+
+    /// The fields before the output value, which it is the hash of.
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut buf = self.signed_fields()?;
+        bytes(&mut buf, &self.signature_value);
+        Ok(buf)
+    }
+
+    /// Check the RSA signature of the pulse with the certificate it names,
+    /// given as PEM with the beacon's own certificate first.
+    ///
+    /// The certificate must hash to the pulse's `certificateId`, the SHA-512
+    /// of either the PEM as served or the DER of its first certificate, so a
+    /// certificate from a spoofed beacon is not trusted.
+    pub fn verify_signature(&self, certificate: &[u8]) -> Result<()> {
+        let invalid = |e: openssl::error::ErrorStack| self.invalid(format!("certificate: {e}"));
+        let cert = X509::stack_from_pem(certificate)
+            .map_err(invalid)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| self.invalid("no certificate in PEM".into()))?;
+        let der = cert.to_der().map_err(invalid)?;
+        if ![Sha512::digest(certificate), Sha512::digest(der)]
+            .iter()
+            .any(|hash| hash.as_slice() == self.certificate_id)
+        {
+            return Err(self.invalid("certificate does not hash to its id".into()));
+        }
+        let key = cert.public_key().map_err(invalid)?;
+        let mut verifier = Verifier::new(MessageDigest::sha512(), &key).map_err(invalid)?;
+        verifier.update(&self.signed_fields()?).map_err(invalid)?;
+        if !verifier.verify(&self.signature_value).unwrap_or(false) {
+            return Err(self.invalid("signature does not match the certificate".into()));
+        }
+        Ok(())
+    }
+
+    /// Check that the output value is the hash of the rest of the pulse.
+    ///
+    /// The signature is checked on its own with [`Self::verify_signature`],
+    /// as that needs the beacon's certificate.
+    pub fn verify_output(&self) -> Result<()> {
+        if Sha512::digest(self.serialize()?).as_slice() != self.output_value {
+            return Err(self.invalid("output value is not the hash of the pulse".into()));
+        }
+        Ok(())
+    }
+
+    /// Check that this pulse follows `prev`, the one before it in its chain:
+    /// it names `prev`'s output as previous, and its local random value is
+    /// the one `prev` committed to.
+    pub fn verify_link(&self, prev: &nist_rand_entry::Model) -> Result<()> {
+        if self.list_value("previous") != Some(prev.output_value.as_slice()) {
+            return Err(self.invalid(format!(
+                "previous output does not match stored pulse {}/{}",
+                prev.chain_index, prev.pulse_index
+            )));
+        }
+        if let Some(precommitment) = &prev.precommitment_value {
+            if Sha512::digest(&self.local_random_value).as_slice() != precommitment {
+                return Err(self.invalid(format!(
+                    "local random value is not the one committed to by pulse {}/{}",
+                    prev.chain_index, prev.pulse_index
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entity::nist_rand_entry;
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        sign::Signer,
+        x509::{X509Builder, X509NameBuilder, X509},
+    };
+    use sha2::{Digest, Sha512};
+
+    use super::{
+        DrandPulse, NistBeaconPulse, NistBeaconRepoErr, NistBeaconResponse, Provider, Pulse,
+    };
+
+    fn drand() -> DrandPulse {
+        serde_json::from_str(include_str!("fixtures/drand_round_4321001.json")).unwrap()
+    }
+
+    /// A key and a self-signed PEM certificate for it.
+    fn certificate() -> (PKey<Private>, Vec<u8>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "beacon").unwrap();
+        let name = name.build();
+        let mut cert = X509Builder::new().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha512()).unwrap();
+        (key, cert.build().to_pem().unwrap())
+    }
+
+    #[test]
+    fn test_hex_bytes() {
+        let parse = |hex: &str| {
+            serde_json::from_str::<DrandPulse>(&format!(
+                r#"{{"round": 1, "randomness": "{hex}", "signature": "00"}}"#
+            ))
+        };
+        assert_eq!(parse("00aB10").unwrap().randomness, [0x00, 0xab, 0x10]);
+        assert!(parse("0").is_err());
+        assert!(parse("+f").is_err());
+        assert!(parse("é").is_err());
+        assert!(parse("aé1").is_err());
+    }
+
+    #[test]
+    fn test_nist_signature() {
+        let mut pulse: NistBeaconPulse = serde_json::from_str::<NistBeaconResponse>(include_str!(
+            "fixtures/nist_pulse_1187001.json"
+        ))
+        .unwrap()
+        .pulse;
+        let (key, pem) = certificate();
+        let sign = |pulse: &mut NistBeaconPulse, certificate_id: Vec<u8>| {
+            pulse.certificate_id = certificate_id;
+            let mut signer = Signer::new(MessageDigest::sha512(), &key).unwrap();
+            signer.update(&pulse.signed_fields().unwrap()).unwrap();
+            pulse.signature_value = signer.sign_to_vec().unwrap();
+        };
+        // Not the certificate the pulse names
+        let id = pulse.certificate_id.clone();
+        sign(&mut pulse, id);
+        assert!(matches!(
+            pulse.verify_signature(&pem),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+
+        let der = X509::from_pem(&pem).unwrap().to_der().unwrap();
+        sign(&mut pulse, Sha512::digest(der).to_vec());
+        pulse.verify_signature(&pem).unwrap();
+        sign(&mut pulse, Sha512::digest(&pem).to_vec());
+        pulse.verify_signature(&pem).unwrap();
+
+        // Named by the pulse, but of another key
+        let (_, other) = certificate();
+        let mut forged = serde_json::from_str::<NistBeaconResponse>(include_str!(
+            "fixtures/nist_pulse_1187001.json"
+        ))
+        .unwrap()
+        .pulse;
+        sign(&mut forged, Sha512::digest(&other).to_vec());
+        assert!(matches!(
+            forged.verify_signature(&other),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+
+        assert!(matches!(
+            pulse.verify_signature(b"not a certificate"),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+        pulse.pulse_index += 1;
+        assert!(matches!(
+            pulse.verify_signature(&pem),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+    }
+
+    #[test]
+    fn test_drand_pulse() {
+        let pulse = drand();
+        pulse.verify_randomness().unwrap();
+        let previous = pulse.previous_signature.clone();
+
+        let pulse = Pulse::Drand {
+            uri: "https://api.drand.sh/public/4321001".into(),
+            fetched_at: time::OffsetDateTime::UNIX_EPOCH,
+            pulse,
+        };
+        let id = pulse.id();
+        assert_eq!((id.provider, id.pulse_index), (Provider::Drand, 4321001));
+        assert_eq!(
+            pulse.output_value().as_slice(),
+            Sha512::digest(&drand().randomness).as_slice()
+        );
+
+        let prev = nist_rand_entry::Model {
+            provider: "drand".into(),
+            chain_index: 1,
+            pulse_index: 4321000,
+            uri: "https://api.drand.sh/public/4321000".into(),
+            timestamp: "1970-01-01T00:00:00Z".into(),
+            output_value: vec![0; 64],
+            version: None,
+            cipher_suite: None,
+            period: None,
+            certificate_id: None,
+            local_random_value: None,
+            external_source_id: None,
+            external_status_code: None,
+            external_value: None,
+            previous_value: None,
+            hour_value: None,
+            day_value: None,
+            month_value: None,
+            year_value: None,
+            precommitment_value: None,
+            status_code: None,
+            signature_value: previous,
+        };
+        pulse.verify_link(&prev).unwrap();
+        let prev = nist_rand_entry::Model {
+            signature_value: Some(vec![0; 96]),
+            ..prev
+        };
+        assert!(matches!(
+            pulse.verify_link(&prev),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+
+        let mut tampered = drand();
+        tampered.randomness[0] ^= 1;
+        assert!(matches!(
+            tampered.verify_randomness(),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+    }
+}
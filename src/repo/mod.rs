@@ -1,2 +1,6 @@
+pub mod beacon;
 pub mod music;
 pub mod nist_beacon;
+pub mod random;
+pub mod roll;
+pub mod roll_macro;
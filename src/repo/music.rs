@@ -49,7 +49,7 @@ impl MusicRepo {
     }
 
     pub async fn leave(mng: Arc<Songbird>, guild_id: GuildId) -> Result<()> {
-        _ = mng.remove(guild_id).await?;
+        mng.remove(guild_id).await?;
         Ok(())
     }
 
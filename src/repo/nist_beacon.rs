@@ -1,31 +1,34 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
 use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec, view::BitView};
 use entity::{prelude::*, *};
-use poise::serenity_prelude::futures::{stream, StreamExt};
-use sea_orm::{ActiveValue, DatabaseConnection, EntityTrait, QueryOrder};
-use serde::Deserialize;
-use serde_hex::{SerHex, StrictCap};
-use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
-
-const URL: &'static str = "https://beacon.nist.gov/beacon/2.0/pulse/last";
-const N_BYTES: usize = 64;
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct NistBeaconResponse {
-    pub pulse: NistBeaconPulse,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct NistBeaconPulse {
-    pub uri: String,
-    pub chain_index: i32,
-    pub pulse_index: i64,
-    #[serde(with = "time::serde::rfc3339")]
-    pub time_stamp: OffsetDateTime,
-    #[serde(with = "SerHex::<StrictCap>")]
-    pub output_value: [u8; N_BYTES],
-}
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use super::beacon::{Beacon, Provider, Pulse};
+
+pub(super) const N_BYTES: usize = 64;
+/// How long a beacon may take to answer before the next one is tried
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the beacon publishes a pulse
+const PULSE_PERIOD: Duration = Duration::from_secs(60);
+/// How long to wait before asking again when the beacon has nothing new
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// How long a roll may wait for a pulse before giving up
+const MAX_WAIT: Duration = Duration::from_secs(3 * 60);
 
 #[derive(Debug, thiserror::Error)]
 pub enum NistBeaconRepoErr {
@@ -35,164 +38,800 @@ pub enum NistBeaconRepoErr {
     DbErr(#[from] sea_orm::DbErr),
     #[error("NistBeaconRepoErr/TimeFmtErr: {0}")]
     TimeFmtErr(#[from] time::error::Format),
+    #[error("NistBeaconRepoErr/TimeParseErr: {0}")]
+    TimeParseErr(#[from] time::error::Parse),
     #[error("NistBeaconRepoErr/NoNewRand: {0}")]
     NoNewRand(String),
+    #[error("NistBeaconRepoErr/ReplayErr: {0}")]
+    ReplayErr(String),
+    #[error("NistBeaconRepoErr/InvalidPulse: {0}")]
+    InvalidPulse(String),
 }
 
 pub type Result<T, E = NistBeaconRepoErr> = std::result::Result<T, E>;
 
+/// Identifies a pulse, as stored in `nist_rand_entry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PulseId {
+    pub provider: Provider,
+    pub chain_index: i32,
+    pub pulse_index: i64,
+}
+
+/// A run of bits consumed from a pulse, `offset` counting from the most
+/// significant bit of its `output_value`, or of its stretched stream if
+/// `drbg` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitSpan {
+    #[serde(default, skip_serializing_if = "Provider::is_nist")]
+    pub provider: Provider,
+    pub chain_index: i32,
+    pub pulse_index: i64,
+    pub offset: u64,
+    pub len: u16,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub drbg: bool,
+}
+
+/// Add `span` to the spans of a draw, extending the last one if adjacent.
+fn push_span(spans: &mut Vec<BitSpan>, span: BitSpan) {
+    if let Some(last) = spans.last_mut() {
+        if (last.provider, last.chain_index, last.pulse_index, last.drbg)
+            == (span.provider, span.chain_index, span.pulse_index, span.drbg)
+            && last.offset + u64::from(last.len) == span.offset
+        {
+            if let Some(len) = last.len.checked_add(span.len) {
+                last.len = len;
+                return;
+            }
+        }
+    }
+    spans.push(span);
+}
+
+/// Key of the ChaCha20 stream a pulse is stretched into.
+fn stretch_key(output_value: &[u8]) -> [u8; 32] {
+    Sha256::digest(output_value).into()
+}
+
+/// Bits `offset..offset + len` of the ChaCha20 stream keyed by `key`, each
+/// word read from its most significant bit.
+///
+/// Any part of the stream can be read back without the bits before it.
+fn stretched_bits(key: [u8; 32], offset: u64, len: usize) -> BitVec<u8, Msb0> {
+    let mut rng = ChaCha20Rng::from_seed(key);
+    rng.set_word_pos(u128::from(offset / 32));
+    let skip = (offset % 32) as usize;
+    let mut bits = BitVec::<u8, Msb0>::with_capacity(skip + len + 32);
+    while bits.len() < skip + len {
+        bits.extend_from_bitslice(rng.next_u32().to_be_bytes().view_bits::<Msb0>());
+    }
+    BitVec::from_bitslice(&bits[skip..skip + len])
+}
+
+/// A source of uniformly random bits, read in order.
+pub trait BitSource {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>>;
+
+    /// A uniformly random integer in `from..=to`, anywhere in `i64`.
+    ///
+    /// Draws just enough bits to cover the range and rejects values past
+    /// its end, so the same bits always give the same result.
+    async fn rand(&mut self, from: i64, to: i64) -> Result<i64> {
+        if to <= from {
+            return Ok(from);
+        }
+        // Fits in a u64 even for the whole of i64
+        let span = (i128::from(to) - i128::from(from)) as u64;
+        let k = u64::BITS - span.leading_zeros();
+
+        loop {
+            let rand_bits = self.pop_bits(k as usize).await?;
+            let num = rand_bits
+                .iter()
+                .fold(0u64, |num, bit| (num << 1) | u64::from(*bit));
+
+            if num <= span {
+                return Ok((i128::from(from) + i128::from(num)) as i64);
+            }
+        }
+    }
+
+    /// `n` uniformly random integers in `from..=to`, read from the same bits
+    /// as `n` calls to [`rand`](Self::rand).
+    async fn rand_many(&mut self, n: usize, from: i64, to: i64) -> Result<Vec<i64>> {
+        let mut nums = Vec::with_capacity(n);
+        for _ in 0..n {
+            nums.push(self.rand(from, to).await?);
+        }
+        Ok(nums)
+    }
+}
+
+/// A pulse fetched ahead of time, not yet read from.
+struct Reserved {
+    id: PulseId,
+    output_value: [u8; N_BYTES],
+}
+
+/// The pulse stretched draws read from, and how far into its stream.
+struct Stretch {
+    id: PulseId,
+    key: [u8; 32],
+    pos: u64,
+    /// When the stream was seeded, to move on to a fresher pulse a period later
+    seeded_at: tokio::time::Instant,
+}
+
+/// Counters of the bits the bot has used since it started.
+#[derive(Debug, Default)]
+struct Metrics {
+    bits_consumed: AtomicU64,
+    pulses_fetched: AtomicU64,
+    waits: AtomicU64,
+    fetch_errors: AtomicU64,
+    /// When the last fetch failed, and why
+    last_error: Mutex<Option<(OffsetDateTime, String)>>,
+}
+
+/// A snapshot of the reserve of pulses and of the [`Metrics`].
+#[derive(Debug, Clone)]
+pub struct BeaconStatus {
+    /// Pulses fetched but not read from yet
+    pub reserve: usize,
+    /// Pulses the prefetcher tries to keep, 0 if it is not running
+    pub target: usize,
+    /// Rolls in line for bits
+    pub waiting: usize,
+    /// When the beacon should publish its next pulse
+    pub next_pulse: Option<OffsetDateTime>,
+    pub bits_consumed: u64,
+    pub pulses_fetched: u64,
+    /// Rolls that had to wait for a pulse
+    pub waits: u64,
+    pub fetch_errors: u64,
+    pub last_error: Option<(OffsetDateTime, String)>,
+    /// Bits left in the queue, `None` while a roll holds it
+    pub queued_bits: Option<usize>,
+}
+
 pub struct NistBeaconRepo {
-    url: String,
+    /// In order of priority
+    beacons: Vec<Beacon>,
+    client: reqwest::Client,
     db: DatabaseConnection,
     bitq: tokio::sync::Mutex<BitQueue>,
+    stretch: tokio::sync::Mutex<Option<Stretch>>,
+    reserve: Mutex<VecDeque<Reserved>>,
+    /// Pulses the prefetcher keeps in `reserve`, 0 until it is started
+    target: AtomicUsize,
+    prefetching: AtomicBool,
+    /// A pulse was added to `reserve`
+    arrived: tokio::sync::Notify,
+    /// A pulse was taken from `reserve`
+    consumed: tokio::sync::Notify,
+    last_pulse: Mutex<Option<OffsetDateTime>>,
+    waiting: AtomicUsize,
+    metrics: Metrics,
+    /// PEM certificates that verified a pulse, by certificate id
+    certificates: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
 }
 
 impl NistBeaconRepo {
-    pub fn new(db: DatabaseConnection) -> Self {
+    pub fn new(db: DatabaseConnection, beacons: Vec<Beacon>) -> Self {
         Self {
-            url: URL.into(),
+            beacons,
+            client: reqwest::Client::builder()
+                .timeout(FETCH_TIMEOUT)
+                .build()
+                .expect("reqwest client builds with a timeout"),
             db,
             bitq: BitQueue::new().into(),
+            stretch: tokio::sync::Mutex::default(),
+            reserve: Mutex::default(),
+            target: AtomicUsize::new(0),
+            prefetching: AtomicBool::new(false),
+            arrived: tokio::sync::Notify::new(),
+            consumed: tokio::sync::Notify::new(),
+            last_pulse: Mutex::default(),
+            waiting: AtomicUsize::new(0),
+            metrics: Metrics::default(),
+            certificates: Mutex::default(),
         }
     }
 
-    #[inline]
-    pub async fn get_nist_current_rand(url: &str) -> Result<NistBeaconPulse> {
-        let res = reqwest::get(url)
-            .await?
-            .json::<NistBeaconResponse>()
-            .await?;
-
-        Ok(res.pulse)
-    }
-
-    pub fn get_new_rand_stream<'a>(
-        nist_url: &'a str,
-        db: DatabaseConnection,
-    ) -> impl StreamExt<Item = Result<[u8; N_BYTES]>> + 'a {
-        stream::unfold((nist_url, db), |(nist_url, db)| async move {
-            macro_rules! shoot {
-                ($x:expr) => {
-                    match $x {
-                        Ok(o) => o,
-                        Err(e) => return Some((Err(e.into()), (nist_url, db))),
-                    }
-                };
+    /// Keep `target` unused pulses in reserve from a background task, so
+    /// that rolls rarely wait on the beacon and never fail because it has
+    /// not published since the last fetch.
+    pub fn spawn_prefetcher(self: Arc<Self>, target: usize) -> tokio::task::JoinHandle<()> {
+        self.target.store(target.max(1), Ordering::Relaxed);
+        self.prefetching.store(true, Ordering::Relaxed);
+        tokio::spawn(async move { self.prefetch().await })
+    }
+
+    async fn prefetch(&self) {
+        loop {
+            let target = self.target.load(Ordering::Relaxed);
+            let consumed = self.consumed.notified();
+            tokio::pin!(consumed);
+            consumed.as_mut().enable();
+            if self.reserve_len() >= target {
+                consumed.await;
+                continue;
             }
-            let curr = shoot! {
-                Self::get_nist_current_rand(nist_url).await
-            };
-            let stored = shoot! {
-                NistRandEntry::find()
-                    .order_by_desc(nist_rand_entry::Column::ChainIndex)
-                    .order_by_desc(nist_rand_entry::Column::PulseIndex)
-                    .one(&db)
-                    .await
-            };
-            if let Some(s) = stored {
-                if curr.chain_index == s.chain_index && curr.pulse_index == s.pulse_index {
-                    return Some((
-                        Err(NistBeaconRepoErr::NoNewRand(format!(
-                            "({},{}) already existed in database",
-                            s.chain_index, s.pulse_index
-                        ))),
-                        (nist_url, db),
-                    ));
+
+            match self.get_new_rand().await {
+                Ok((id, time_stamp, output_value)) => {
+                    self.add_to_reserve(id, time_stamp, output_value);
+
+                    let status = self.status();
+                    tracing::debug!(
+                        "reserved pulse {}/{}, {}/{} in reserve, {} bits used by {} pulses, {} waits, {} fetch errors",
+                        id.chain_index,
+                        id.pulse_index,
+                        status.reserve,
+                        status.target,
+                        status.bits_consumed,
+                        status.pulses_fetched,
+                        status.waits,
+                        status.fetch_errors,
+                    );
+
+                    // Nothing new until the next pulse is published
+                    let next = time_stamp + PULSE_PERIOD - OffsetDateTime::now_utc();
+                    let next = Duration::try_from(next).unwrap_or_default();
+                    tokio::time::sleep(next.min(PULSE_PERIOD)).await;
+                }
+                Err(NistBeaconRepoErr::NoNewRand(_)) => tokio::time::sleep(RETRY_DELAY).await,
+                Err(e) => {
+                    tracing::warn!("could not prefetch a beacon pulse: {e}");
+                    tokio::time::sleep(RETRY_DELAY).await;
                 }
             }
+        }
+    }
 
-            let new_store = nist_rand_entry::ActiveModel {
-                chain_index: ActiveValue::set(curr.chain_index),
-                pulse_index: ActiveValue::set(curr.pulse_index),
-                timestamp: ActiveValue::set(shoot!(curr
-                    .time_stamp
-                    .to_offset(UtcOffset::UTC)
-                    .format(&Rfc3339))),
-                uri: ActiveValue::set(curr.uri),
-                output_value: ActiveValue::set(curr.output_value.to_vec()),
+    /// Put a freshly fetched pulse in reserve, waking a roll waiting for it.
+    fn add_to_reserve(&self, id: PulseId, time_stamp: OffsetDateTime, output_value: [u8; N_BYTES]) {
+        self.metrics.pulses_fetched.fetch_add(1, Ordering::Relaxed);
+        *self
+            .last_pulse
+            .lock()
+            .expect("last pulse lock is not poisoned") = Some(time_stamp);
+        self.lock_reserve().push_back(Reserved { id, output_value });
+        self.arrived.notify_waiters();
+    }
+
+    fn lock_reserve(&self) -> std::sync::MutexGuard<'_, VecDeque<Reserved>> {
+        self.reserve.lock().expect("reserve lock is not poisoned")
+    }
+
+    fn reserve_len(&self) -> usize {
+        self.lock_reserve().len()
+    }
+
+    /// How much is in reserve and how many bits were used so far.
+    pub fn status(&self) -> BeaconStatus {
+        let last_pulse = *self
+            .last_pulse
+            .lock()
+            .expect("last pulse lock is not poisoned");
+        BeaconStatus {
+            reserve: self.reserve_len(),
+            target: self.target.load(Ordering::Relaxed),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            next_pulse: last_pulse.map(|t| t + PULSE_PERIOD),
+            bits_consumed: self.metrics.bits_consumed.load(Ordering::Relaxed),
+            pulses_fetched: self.metrics.pulses_fetched.load(Ordering::Relaxed),
+            waits: self.metrics.waits.load(Ordering::Relaxed),
+            fetch_errors: self.metrics.fetch_errors.load(Ordering::Relaxed),
+            last_error: self
+                .metrics
+                .last_error
+                .lock()
+                .expect("last error lock is not poisoned")
+                .clone(),
+            queued_bits: self.bitq.try_lock().ok().map(|bitq| bitq.len()),
+        }
+    }
+
+    /// Count a failed fetch, keeping its error to show in the status.
+    fn record_error(&self, provider: Provider, e: &NistBeaconRepoErr) {
+        self.metrics.fetch_errors.fetch_add(1, Ordering::Relaxed);
+        *self
+            .metrics
+            .last_error
+            .lock()
+            .expect("last error lock is not poisoned") = Some((
+            OffsetDateTime::now_utc(),
+            format!("{}: {e}", provider.name()),
+        ));
+    }
+
+    /// The beacons pulses are fetched from, in order of priority.
+    pub fn providers(&self) -> impl Iterator<Item = Provider> + '_ {
+        self.beacons.iter().map(|b| b.provider)
+    }
+
+    /// The latest pulse stored from `provider`.
+    pub async fn latest_stored(
+        &self,
+        provider: Provider,
+    ) -> Result<Option<nist_rand_entry::Model>> {
+        Self::latest_entry(provider, &self.db).await
+    }
+
+    async fn latest_entry(
+        provider: Provider,
+        db: &DatabaseConnection,
+    ) -> Result<Option<nist_rand_entry::Model>> {
+        Ok(NistRandEntry::find()
+            .filter(nist_rand_entry::Column::Provider.eq(provider.key()))
+            .order_by_desc(nist_rand_entry::Column::ChainIndex)
+            .order_by_desc(nist_rand_entry::Column::PulseIndex)
+            .one(db)
+            .await?)
+    }
+
+    /// How many pulses are stored, from every beacon.
+    pub async fn stored_count(&self) -> Result<u64> {
+        Ok(NistRandEntry::find().count(&self.db).await?)
+    }
+
+    /// A stored pulse, if it was ever fetched.
+    pub async fn stored_pulse(&self, id: PulseId) -> Result<Option<nist_rand_entry::Model>> {
+        Ok(
+            NistRandEntry::find_by_id((id.provider.key().into(), id.chain_index, id.pulse_index))
+                .one(&self.db)
+                .await?,
+        )
+    }
+
+    /// The next pulse to read from, waiting in line for the prefetcher when
+    /// the reserve is empty.
+    async fn next_pulse(&self) -> Result<Reserved> {
+        if !self.prefetching.load(Ordering::Relaxed) {
+            let (id, _, output_value) = self.get_new_rand().await?;
+            return Ok(Reserved { id, output_value });
+        }
+
+        let deadline = tokio::time::Instant::now() + MAX_WAIT;
+        let mut waited = false;
+        loop {
+            let arrived = self.arrived.notified();
+            tokio::pin!(arrived);
+            arrived.as_mut().enable();
+            if let Some(pulse) = self.take_reserved() {
+                return Ok(pulse);
+            }
+            self.wait_arrived(arrived, deadline, &mut waited).await?;
+        }
+    }
+
+    /// The oldest pulse in reserve, making room for the prefetcher.
+    fn take_reserved(&self) -> Option<Reserved> {
+        let pulse = self.lock_reserve().pop_front();
+        if pulse.is_some() {
+            self.consumed.notify_one();
+        }
+        pulse
+    }
+
+    /// Wait for the prefetcher to reserve a pulse, counting a roll's first
+    /// wait.
+    async fn wait_arrived(
+        &self,
+        arrived: std::pin::Pin<&mut tokio::sync::futures::Notified<'_>>,
+        deadline: tokio::time::Instant,
+        waited: &mut bool,
+    ) -> Result<()> {
+        if !*waited {
+            *waited = true;
+            self.metrics.waits.fetch_add(1, Ordering::Relaxed);
+        }
+        tokio::time::timeout_at(deadline, arrived)
+            .await
+            .map_err(|_| {
+                NistBeaconRepoErr::NoNewRand(format!(
+                    "no pulse arrived within {}s",
+                    MAX_WAIT.as_secs()
+                ))
+            })
+    }
+
+    /// The queue's lock, once the queue holds `n` bits.
+    ///
+    /// A roll short of bits waits for the next pulse without the lock, so
+    /// rolls the queued bits cover are not held up behind it.
+    async fn lock_queue(&self, n: usize) -> Result<tokio::sync::MutexGuard<'_, BitQueue>> {
+        let deadline = tokio::time::Instant::now() + MAX_WAIT;
+        let mut waited = false;
+        loop {
+            let arrived = self.arrived.notified();
+            tokio::pin!(arrived);
+            arrived.as_mut().enable();
+            let mut bitq = self.bitq.lock().await;
+            if self.fill_from_reserve(&mut bitq, n) {
+                return Ok(bitq);
+            }
+            if !self.prefetching.load(Ordering::Relaxed) {
+                while bitq.len() < n {
+                    let (id, _, output_value) = self.get_new_rand().await?;
+                    bitq.insert_pulse(id, &output_value);
+                }
+                return Ok(bitq);
+            }
+            drop(bitq);
+            self.wait_arrived(arrived, deadline, &mut waited).await?;
+        }
+    }
+
+    /// Move pulses from the reserve to the queue until it holds `n` bits,
+    /// returning whether it does.
+    fn fill_from_reserve(&self, bitq: &mut BitQueue, n: usize) -> bool {
+        while bitq.len() < n {
+            let Some(pulse) = self.take_reserved() else {
+                return false;
             };
-            _ = shoot! {
-                NistRandEntry::insert(new_store).exec(&db).await
+            bitq.insert_pulse(pulse.id, &pulse.output_value);
+        }
+        true
+    }
+
+    /// Fetch the latest pulse of the first beacon that answers, and store
+    /// it unless it is already stored.
+    ///
+    /// Beacons are tried in order, the next one whenever a beacon fails for
+    /// any reason, including having nothing new. If they all fail, the
+    /// error is `NoNewRand` when some beacon merely had nothing new.
+    pub async fn get_new_rand(&self) -> Result<(PulseId, OffsetDateTime, [u8; N_BYTES])> {
+        let mut no_new = None;
+        let mut last_err = None;
+        for beacon in &self.beacons {
+            let stored = match beacon.latest(&self.client).await {
+                Ok(pulse) => match self.verify_signature(beacon, &pulse).await {
+                    Ok(()) => Self::store_pulse(pulse, &self.db).await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
             };
+            match stored {
+                Ok(rand) => return Ok(rand),
+                Err(e @ NistBeaconRepoErr::NoNewRand(_)) => no_new = Some(e),
+                Err(e) => {
+                    tracing::warn!("beacon {} failed: {e}", beacon.provider.name());
+                    self.record_error(beacon.provider, &e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(no_new
+            .or(last_err)
+            .unwrap_or(NistBeaconRepoErr::NoNewRand("no beacon configured".into())))
+    }
 
-            Some((Ok(curr.output_value), (nist_url, db)))
-        })
+    /// Check the signature of a NIST-format pulse, fetching the certificate
+    /// it names from its beacon the first time. Certificates are cached by
+    /// their hash once they are checked against it.
+    async fn verify_signature(&self, beacon: &Beacon, pulse: &Pulse) -> Result<()> {
+        let Pulse::Nist { pulse, .. } = pulse else {
+            return Ok(());
+        };
+        let cached = self.lock_certificates().get(&pulse.certificate_id).cloned();
+        match cached {
+            Some(certificate) => pulse.verify_signature(&certificate),
+            None => {
+                let certificate = beacon
+                    .certificate(&self.client, &pulse.certificate_id)
+                    .await?;
+                pulse.verify_signature(&certificate)?;
+                self.lock_certificates()
+                    .insert(pulse.certificate_id.clone(), certificate);
+                Ok(())
+            }
+        }
     }
 
-    pub async fn rand(&self, from: i64, to: i64) -> Result<i64> {
-        Self::_rand(
-            &self.bitq,
-            from,
-            to,
-            Self::get_new_rand_stream(&self.url, self.db.clone()),
-        )
-        .await
+    fn lock_certificates(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, Vec<u8>>> {
+        self.certificates
+            .lock()
+            .expect("certificates lock is not poisoned")
+    }
+
+    /// Verify a pulse and store it, unless it is already stored.
+    ///
+    /// Its link to the previous pulse is only checked if that one is the
+    /// last stored, as the bot need not fetch every pulse.
+    async fn store_pulse(
+        curr: Pulse,
+        db: &DatabaseConnection,
+    ) -> Result<(PulseId, OffsetDateTime, [u8; N_BYTES])> {
+        let id = curr.id();
+        let stored = Self::latest_entry(id.provider, db).await?;
+        if let Some(s) = &stored {
+            if id.chain_index == s.chain_index && id.pulse_index == s.pulse_index {
+                return Err(NistBeaconRepoErr::NoNewRand(format!(
+                    "{} ({},{}) already existed in database",
+                    s.provider, s.chain_index, s.pulse_index
+                )));
+            }
+        }
+
+        curr.verify()?;
+        if let Some(s) = stored
+            .filter(|s| s.chain_index == id.chain_index && s.pulse_index + 1 == id.pulse_index)
+        {
+            curr.verify_link(&s)?;
+        }
+        let time_stamp = curr.time_stamp()?;
+        let output_value = curr.output_value();
+        NistRandEntry::insert(curr.into_entry()?).exec(db).await?;
+
+        Ok((id, time_stamp, output_value))
     }
 
-    async fn _rand(
-        bitq: &tokio::sync::Mutex<BitQueue>,
-        from: i64,
-        to: i64,
-        new_rand: impl StreamExt<Item = Result<[u8; N_BYTES]>>,
-    ) -> Result<i64> {
-        tokio::pin!(new_rand);
+    /// Start drawing bits for a roll.
+    pub fn draw(&self) -> Draw<'_> {
+        Draw {
+            repo: self,
+            spans: vec![],
+            stretched: false,
+        }
+    }
 
-        let n = to - from + 1;
-        if n <= 1 {
-            return Ok(from);
+    /// Start drawing bits for a roll from the stream the latest pulse seeds,
+    /// so that one pulse serves any number of rolls.
+    pub fn draw_stretched(&self) -> Draw<'_> {
+        Draw {
+            stretched: true,
+            ..self.draw()
         }
-        let n = n as u32;
+    }
+
+    /// Bits popped from the shared queue, taking a new pulse when short.
+    ///
+    /// Rolls the queued bits cover wait their turn on the queue's lock, so
+    /// they are served in order.
+    async fn pop_bits(&self, n: usize, spans: &mut Vec<BitSpan>) -> Result<BitVec<u8, Msb0>> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let res = self
+            .lock_queue(n)
+            .await
+            .map(|mut bitq| self.pop_queued(&mut bitq, n, spans));
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
 
-        let k = {
-            let y = n.ilog2();
+    /// Bits read from the stream of the current pulse, moving on to a fresher
+    /// pulse about once a period.
+    async fn pop_stretched(&self, n: usize, spans: &mut Vec<BitSpan>) -> Result<BitVec<u8, Msb0>> {
+        self.pop_stretched_locked(&mut *self.stretch.lock().await, n, spans)
+            .await
+    }
 
-            // Check if n is power of 2
-            if n & (n - 1) == 0 {
-                y
-            } else {
-                y + 1
+    async fn pop_stretched_locked(
+        &self,
+        stretch: &mut Option<Stretch>,
+        n: usize,
+        spans: &mut Vec<BitSpan>,
+    ) -> Result<BitVec<u8, Msb0>> {
+        let pulse = match stretch {
+            None => {
+                self.waiting.fetch_add(1, Ordering::Relaxed);
+                let pulse = self.next_pulse().await;
+                self.waiting.fetch_sub(1, Ordering::Relaxed);
+                Some(pulse?)
             }
+            // A period on, the stream moves to the next pulse in reserve, even
+            // the last one, as raw draws can wait for another. It only keeps
+            // to its pulse while the reserve is empty
+            Some(s) if s.seeded_at.elapsed() >= PULSE_PERIOD => self.take_reserved(),
+            Some(_) => None,
         };
+        if let Some(pulse) = pulse {
+            *stretch = Some(Stretch {
+                id: pulse.id,
+                key: stretch_key(&pulse.output_value),
+                pos: 0,
+                seeded_at: tokio::time::Instant::now(),
+            });
+        }
 
-        macro_rules! pop {
-            ($a:expr) => {{
-                let mut bitq = bitq.lock().await;
-                if $a > bitq.len() {
-                    let new = new_rand
-                        .next()
-                        .await
-                        .unwrap_or(Err(NistBeaconRepoErr::NoNewRand("stream ended".into())))?;
-                    bitq.insert_new_rand(&new);
-                }
-                bitq.pop_front($a)
-            }};
+        let s = stretch
+            .as_mut()
+            .expect("a pulse was just taken if there was none");
+        let bits = stretched_bits(s.key, s.pos, n);
+        push_span(
+            spans,
+            BitSpan {
+                provider: s.id.provider,
+                chain_index: s.id.chain_index,
+                pulse_index: s.id.pulse_index,
+                offset: s.pos,
+                len: n as u16,
+                drbg: true,
+            },
+        );
+        s.pos += n as u64;
+        self.metrics
+            .bits_consumed
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(bits)
+    }
+
+    /// `n` bits from a queue that holds them.
+    fn pop_queued(
+        &self,
+        bitq: &mut BitQueue,
+        n: usize,
+        spans: &mut Vec<BitSpan>,
+    ) -> BitVec<u8, Msb0> {
+        for span in bitq.spans(n) {
+            push_span(spans, span);
         }
+        self.metrics
+            .bits_consumed
+            .fetch_add(n as u64, Ordering::Relaxed);
+        bitq.pop_front(n)
+    }
 
-        let mut rand_bits = pop!(k as usize);
-        loop {
-            // dbg!(&rand_bits);
-            let num = {
-                let mut dst = [0u8; 4];
-                for (i, bit) in rand_bits.iter().rev().enumerate() {
-                    let dst_i = 3 - i / 8;
-                    let bitmask = if *bit { 1u8 << i % 8 } else { 0 };
-                    // dbg!(i, *bit, dst_i, bitmask);
-                    dst[dst_i] |= bitmask;
+    /// Rebuild the bits recorded in `spans` from the stored pulses.
+    pub async fn replay(&self, spans: &[BitSpan]) -> Result<Replay> {
+        let mut pulses: Vec<nist_rand_entry::Model> = vec![];
+        let mut bits = BitVec::new();
+        for span in spans {
+            let key = (span.provider.key(), span.chain_index, span.pulse_index);
+            let pulse = match pulses
+                .iter()
+                .find(|p| (p.provider.as_str(), p.chain_index, p.pulse_index) == key)
+            {
+                Some(p) => p,
+                None => {
+                    let p = NistRandEntry::find_by_id((key.0.into(), key.1, key.2))
+                        .one(&self.db)
+                        .await?
+                        .ok_or_else(|| {
+                            NistBeaconRepoErr::ReplayErr(format!("pulse {key:?} is not stored"))
+                        })?;
+                    pulses.push(p);
+                    pulses.last().expect("just pushed")
                 }
+            };
 
-                // dbg!(dst);
+            if span.drbg {
+                let key = stretch_key(&pulse.output_value);
+                bits.extend_from_bitslice(&stretched_bits(key, span.offset, span.len.into()));
+                continue;
+            }
+            let start = span.offset as usize;
+            let end = start + usize::from(span.len);
+            let pulse_bits = pulse.output_value.view_bits::<Msb0>();
+            if end > pulse_bits.len() {
+                return Err(NistBeaconRepoErr::ReplayErr(format!(
+                    "bits {start}..{end} are past the end of pulse {key:?}"
+                )));
+            }
+            bits.extend_from_bitslice(&pulse_bits[start..end]);
+        }
+
+        Ok(Replay {
+            bits,
+            i: 0,
+            uris: pulses.into_iter().map(|p| p.uri).collect(),
+        })
+    }
+}
+
+/// Bits drawn from the beacon for one roll, recording where each came from.
+///
+/// Concurrent rolls share the queue, so a roll's bits need not be adjacent.
+pub struct Draw<'a> {
+    repo: &'a NistBeaconRepo,
+    spans: Vec<BitSpan>,
+    stretched: bool,
+}
+
+impl Draw<'_> {
+    pub fn into_spans(self) -> Vec<BitSpan> {
+        self.spans
+    }
+}
 
-                u32::from_be_bytes(dst)
+impl BitSource for Draw<'_> {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        if self.stretched {
+            self.repo.pop_stretched(n, &mut self.spans).await
+        } else {
+            self.repo.pop_bits(n, &mut self.spans).await
+        }
+    }
+
+    /// Takes every bit under one lock, so no other roll draws in between,
+    /// unless the roll has to wait for a pulse.
+    async fn rand_many(&mut self, n: usize, from: i64, to: i64) -> Result<Vec<i64>> {
+        let repo = self.repo;
+        if self.stretched {
+            let mut locked = Locked {
+                repo,
+                spans: &mut self.spans,
+                guard: Guard::Stretch(repo.stretch.lock().await),
             };
-            // dbg!(num, n);
+            return locked.rand_many(n, from, to).await;
+        }
+
+        repo.waiting.fetch_add(1, Ordering::Relaxed);
+        let mut locked = Locked {
+            repo,
+            spans: &mut self.spans,
+            guard: Guard::Queue(Some(repo.bitq.lock().await)),
+        };
+        let res = locked.rand_many(n, from, to).await;
+        repo.waiting.fetch_sub(1, Ordering::Relaxed);
+        res
+    }
+}
+
+/// A draw holding the lock on where its bits come from.
+struct Locked<'a, 'b> {
+    repo: &'a NistBeaconRepo,
+    spans: &'b mut Vec<BitSpan>,
+    guard: Guard<'a>,
+}
 
-            if num < n {
-                return Ok(from + (num as i64));
+enum Guard<'a> {
+    /// Let go while waiting for a pulse
+    Queue(Option<tokio::sync::MutexGuard<'a, BitQueue>>),
+    Stretch(tokio::sync::MutexGuard<'a, Option<Stretch>>),
+}
+
+impl BitSource for Locked<'_, '_> {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        match &mut self.guard {
+            Guard::Queue(slot) => {
+                if !slot
+                    .as_mut()
+                    .is_some_and(|bitq| self.repo.fill_from_reserve(bitq, n))
+                {
+                    *slot = None;
+                    *slot = Some(self.repo.lock_queue(n).await?);
+                }
+                let bitq = slot.as_mut().expect("the queue was just locked");
+                Ok(self.repo.pop_queued(bitq, n, self.spans))
             }
+            Guard::Stretch(stretch) => self.repo.pop_stretched_locked(stretch, n, self.spans).await,
+        }
+    }
+}
+
+/// The bits of an earlier draw, read back from the stored pulses.
+pub struct Replay {
+    bits: BitVec<u8, Msb0>,
+    i: usize,
+    uris: Vec<String>,
+}
+
+impl Replay {
+    /// Bits that were recorded but not read back.
+    pub fn remaining(&self) -> usize {
+        self.bits.len() - self.i
+    }
+
+    /// URIs of the pulses the bits came from.
+    pub fn uris(&self) -> &[String] {
+        &self.uris
+    }
+}
 
-            rand_bits = pop!(k as usize);
-            // dbg!(&rand_bits);
+impl BitSource for Replay {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        if n > self.remaining() {
+            return Err(NistBeaconRepoErr::ReplayErr(
+                "the roll needs more bits than were recorded".into(),
+            ));
         }
+        let bits = BitVec::from_bitslice(&self.bits[self.i..self.i + n]);
+        self.i += n;
+        Ok(bits)
     }
 }
 
@@ -202,6 +841,8 @@ struct BitQueue {
     i: usize,
     n: usize,
     start_at_zero: bool,
+    /// The pulse held in each half of `arr`
+    pulses: [Option<PulseId>; 2],
 }
 impl BitQueue {
     #[inline]
@@ -211,6 +852,7 @@ impl BitQueue {
             i: 0,
             n: 0,
             start_at_zero: true,
+            pulses: [None; 2],
         }
     }
 
@@ -227,10 +869,7 @@ impl BitQueue {
     fn insert_new_rand(&mut self, new_rand: &[u8; N_BYTES]) {
         let data_i_start = if self.start_at_zero { 0usize } else { N_BYTES };
 
-        for i in 0..N_BYTES {
-            let di = data_i_start + i;
-            self.arr[di] = new_rand[i];
-        }
+        self.arr[data_i_start..data_i_start + N_BYTES].copy_from_slice(new_rand);
 
         self.start_at_zero = !self.start_at_zero;
         self.n += N_BYTES * 8;
@@ -238,6 +877,38 @@ impl BitQueue {
         // dbg!(self.arr);
     }
 
+    fn insert_pulse(&mut self, id: PulseId, new_rand: &[u8; N_BYTES]) {
+        let half = if self.start_at_zero { 0 } else { 1 };
+        self.pulses[half] = Some(id);
+        self.insert_new_rand(new_rand);
+    }
+
+    /// Where the next `n` bits to be popped come from.
+    fn spans(&self, n: usize) -> Vec<BitSpan> {
+        assert!(n <= self.len());
+
+        let half_len = N_BYTES * 8;
+        let mut spans = vec![];
+        let (mut pos, mut left) = (self.i, n);
+        while left > 0 {
+            let pos_in = pos % (half_len * 2);
+            let (half, offset) = (pos_in / half_len, pos_in % half_len);
+            let len = left.min(half_len - offset);
+            let id = self.pulses[half].expect("queued bits come from an inserted pulse");
+            spans.push(BitSpan {
+                provider: id.provider,
+                chain_index: id.chain_index,
+                pulse_index: id.pulse_index,
+                offset: offset as u64,
+                len: len as u16,
+                drbg: false,
+            });
+            pos += len;
+            left -= len;
+        }
+        spans
+    }
+
     fn pop_front(&mut self, n: usize) -> BitVec<u8, Msb0> {
         assert!(n <= self.len());
 
@@ -263,7 +934,67 @@ impl BitQueue {
 
 #[cfg(test)]
 mod tests {
-    use super::{BitQueue, N_BYTES};
+    use bitvec::{bitvec, order::Msb0};
+    use entity::{nist_rand_entry, prelude::*};
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::{ActiveValue, Database, DatabaseConnection, EntityTrait, IntoActiveModel};
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+    use time::OffsetDateTime;
+
+    use crate::repo::{
+        beacon::{Beacon, NistBeaconPulse, NistBeaconResponse, Provider, Pulse},
+        random::{RandomSources, SourceKind},
+    };
+
+    use super::{
+        stretched_bits, BitQueue, BitSource, BitSpan, Draw, NistBeaconRepo, NistBeaconRepoErr,
+        PulseId, N_BYTES, PULSE_PERIOD,
+    };
+
+    fn fixture(json: &str) -> NistBeaconPulse {
+        serde_json::from_str::<NistBeaconResponse>(json)
+            .unwrap()
+            .pulse
+    }
+
+    fn nist(pulse: NistBeaconPulse) -> Pulse {
+        Pulse::Nist {
+            provider: Provider::Nist,
+            pulse: Box::new(pulse),
+        }
+    }
+
+    /// A migrated database holding NIST pulse 2/10 with `output_value`.
+    async fn test_db_with_pulse(output_value: &[u8]) -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        NistRandEntry::insert(nist_rand_entry::ActiveModel {
+            provider: ActiveValue::set("nist".into()),
+            chain_index: ActiveValue::set(2),
+            pulse_index: ActiveValue::set(10),
+            uri: ActiveValue::set("https://beacon.nist.gov/beacon/2.0/chain/2/pulse/10".into()),
+            timestamp: ActiveValue::set("2024-05-01T00:00:00Z".into()),
+            output_value: ActiveValue::set(output_value.to_vec()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+        db
+    }
+
+    /// Two consecutive pulses in the Beacon 2.0 layout, hashed and linked
+    /// like real ones but not signed by NIST; `test_live_nist` checks real
+    /// pulses.
+    fn pulses() -> (NistBeaconPulse, NistBeaconPulse) {
+        (
+            fixture(include_str!("fixtures/nist_pulse_1187001.json")),
+            fixture(include_str!("fixtures/nist_pulse_1187002.json")),
+        )
+    }
 
     #[test]
     fn test_bitqueue_simple() {
@@ -432,4 +1163,556 @@ mod tests {
         assert_eq!(bq.len(), N_BYTES * 8 - 4);
         assert!(!bq.start_at_zero);
     }
+
+    #[test]
+    fn test_bitqueue_spans() {
+        let mut bq = BitQueue::new();
+        let a = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 10,
+        };
+        let b = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 11,
+        };
+        let span = |id: PulseId, offset, len| BitSpan {
+            provider: Provider::Nist,
+            chain_index: id.chain_index,
+            pulse_index: id.pulse_index,
+            offset,
+            len,
+            drbg: false,
+        };
+
+        bq.insert_pulse(a, &[0u8; N_BYTES]);
+        _ = bq.pop_front(N_BYTES * 8 - 3);
+        assert_eq!(bq.spans(3), [span(a, 509, 3)]);
+
+        bq.insert_pulse(b, &[0u8; N_BYTES]);
+        assert_eq!(bq.spans(7), [span(a, 509, 3), span(b, 0, 4)]);
+        _ = bq.pop_front(7);
+        assert_eq!(bq.spans(5), [span(b, 4, 5)]);
+
+        // Wraps back to the first half once the second is used up
+        _ = bq.pop_front(N_BYTES * 8 - 4 - 2);
+        bq.insert_pulse(a, &[0u8; N_BYTES]);
+        assert_eq!(bq.spans(4), [span(b, 510, 2), span(a, 0, 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_reserve() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let repo = NistBeaconRepo::new(db, vec![]);
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let id = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 10,
+        };
+        let mut output_value = [0u8; N_BYTES];
+        output_value[0] = 0b1011_0000;
+
+        let mut draw = repo.draw();
+        let (bits, _) = tokio::join!(draw.pop_bits(4), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(repo.status().waiting, 1);
+            repo.add_to_reserve(id, OffsetDateTime::UNIX_EPOCH, output_value);
+        });
+        assert_eq!(bits.unwrap(), bitvec![u8, Msb0; 1, 0, 1, 1]);
+        assert_eq!(
+            draw.into_spans(),
+            [BitSpan {
+                provider: Provider::Nist,
+                chain_index: 2,
+                pulse_index: 10,
+                offset: 0,
+                len: 4,
+                drbg: false,
+            }]
+        );
+
+        let status = repo.status();
+        assert_eq!((status.waiting, status.reserve), (0, 0));
+        assert_eq!((status.bits_consumed, status.waits), (4, 1));
+
+        // The rest of the pulse is still queued
+        assert_eq!(repo.draw().pop_bits(508).await.unwrap().len(), 508);
+        assert_eq!(repo.status().waits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_without_queue() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let repo = NistBeaconRepo::new(db, vec![]);
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let id = |pulse_index| PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index,
+        };
+        repo.add_to_reserve(id(10), OffsetDateTime::UNIX_EPOCH, [0; N_BYTES]);
+        repo.draw().pop_bits(500).await.unwrap();
+
+        // A roll short of bits does not hold up one the queue still covers
+        let (mut short, mut covered) = (repo.draw(), repo.draw());
+        {
+            let waiting = short.rand_many(4, 0, 0xffff);
+            tokio::pin!(waiting);
+            tokio::select! {
+                biased;
+                _ = &mut waiting => panic!("drew without a pulse"),
+                bits = covered.pop_bits(8) => assert_eq!(bits.unwrap().len(), 8),
+            }
+            assert_eq!(repo.status().waiting, 1);
+            repo.add_to_reserve(id(11), OffsetDateTime::UNIX_EPOCH, [0; N_BYTES]);
+            assert_eq!(waiting.await.unwrap().len(), 4);
+        }
+
+        let spans = |draw: Draw| {
+            draw.into_spans()
+                .iter()
+                .map(|s| (s.pulse_index, s.offset, s.len))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spans(covered), [(10, 500, 8)]);
+        assert_eq!(spans(short), [(10, 508, 4), (11, 0, 60)]);
+        assert_eq!(repo.status().waits, 1);
+    }
+
+    #[test]
+    fn test_stretched_bits() {
+        let key = [7u8; 32];
+        let all = stretched_bits(key, 0, 200);
+        assert_eq!(all.len(), 200);
+        for (offset, len) in [(0, 1), (5, 27), (31, 2), (37, 100), (64, 64)] {
+            assert_eq!(
+                stretched_bits(key, offset, len),
+                all[offset as usize..offset as usize + len]
+            );
+        }
+        assert_ne!(stretched_bits([8u8; 32], 0, 200), all);
+    }
+
+    #[tokio::test]
+    async fn test_stretched_replay() {
+        let output_value = [0x5au8; N_BYTES];
+        let db = test_db_with_pulse(&output_value).await;
+        let repo = NistBeaconRepo::new(db, vec![]);
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let id = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 10,
+        };
+        repo.add_to_reserve(id, OffsetDateTime::UNIX_EPOCH, output_value);
+
+        // Far more bits than the pulse holds, from a single pulse
+        let mut drawn = vec![];
+        for _ in 0..3 {
+            let mut draw = repo.draw_stretched();
+            let mut bits = draw.pop_bits(300).await.unwrap();
+            bits.extend_from_bitslice(&draw.pop_bits(20).await.unwrap());
+            drawn.push((bits, draw.into_spans()));
+        }
+        let (bits, spans) = &drawn[2];
+        assert_eq!(
+            spans,
+            &[BitSpan {
+                provider: Provider::Nist,
+                chain_index: 2,
+                pulse_index: 10,
+                offset: 640,
+                len: 320,
+                drbg: true,
+            }]
+        );
+
+        let mut replay = repo.replay(spans).await.unwrap();
+        assert_eq!(&replay.pop_bits(320).await.unwrap(), bits);
+        assert_ne!(drawn[0].0, drawn[1].0);
+    }
+
+    #[tokio::test]
+    async fn test_stretched_reseed() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let repo = NistBeaconRepo::new(db, vec![]);
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let reserve = |pulse_index| {
+            let id = PulseId {
+                provider: Provider::Nist,
+                chain_index: 2,
+                pulse_index,
+            };
+            repo.add_to_reserve(id, OffsetDateTime::UNIX_EPOCH, [pulse_index as u8; N_BYTES]);
+        };
+        reserve(10);
+        reserve(11);
+        let pulse_of = |draw: Draw| draw.into_spans()[0].pulse_index;
+        let age = || async {
+            let mut stretch = repo.stretch.lock().await;
+            let s = stretch.as_mut().unwrap();
+            s.seeded_at = tokio::time::Instant::now()
+                .checked_sub(PULSE_PERIOD)
+                .unwrap();
+        };
+
+        let mut draw = repo.draw_stretched();
+        draw.pop_bits(8).await.unwrap();
+        assert_eq!(pulse_of(draw), 10);
+
+        // The same pulse serves every roll within a period
+        let mut draw = repo.draw_stretched();
+        draw.pop_bits(8).await.unwrap();
+        draw.pop_bits(8).await.unwrap();
+        assert_eq!(pulse_of(draw), 10);
+        assert_eq!(repo.reserve_len(), 1);
+
+        // A period on, even a reserve of one pulse is enough to move on
+        age().await;
+        let mut draw = repo.draw_stretched();
+        draw.pop_bits(8).await.unwrap();
+        assert_eq!(pulse_of(draw), 11);
+        assert_eq!(repo.reserve_len(), 0);
+
+        // Rolls go on from the old pulse until the next one arrives
+        age().await;
+        let mut draw = repo.draw_stretched();
+        draw.pop_bits(8).await.unwrap();
+        assert_eq!(pulse_of(draw), 11);
+        reserve(12);
+        let mut draw = repo.draw_stretched();
+        draw.pop_bits(8).await.unwrap();
+        assert_eq!(pulse_of(draw), 12);
+    }
+
+    #[tokio::test]
+    async fn test_store_pulse() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let (a, b) = pulses();
+        a.verify_output().unwrap();
+        b.verify_output().unwrap();
+
+        let (id, time_stamp, _) = NistBeaconRepo::store_pulse(nist(a), &db).await.unwrap();
+        assert_eq!(id.pulse_index, 1187001);
+        assert_eq!(time_stamp.unix_timestamp(), 1714564800);
+        assert!(matches!(
+            NistBeaconRepo::store_pulse(nist(pulses().0), &db).await,
+            Err(NistBeaconRepoErr::NoNewRand(_))
+        ));
+        NistBeaconRepo::store_pulse(nist(b), &db).await.unwrap();
+
+        let stored = NistRandEntry::find_by_id(("nist".into(), 2, 1187001))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.timestamp, "2024-05-01T12:00:00.000Z");
+        assert_eq!(stored.signature_value.map(|s| s.len()), Some(512));
+        assert_eq!(stored.certificate_id.map(|s| s.len()), Some(64));
+        assert!(stored.year_value.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_beacon_status() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let (a, b) = pulses();
+        NistBeaconRepo::store_pulse(nist(a), &db).await.unwrap();
+        NistBeaconRepo::store_pulse(nist(b), &db).await.unwrap();
+        let repo = NistBeaconRepo::new(db, vec![Beacon::new(Provider::Nist)]);
+
+        assert_eq!(repo.providers().collect::<Vec<_>>(), [Provider::Nist]);
+        assert_eq!(repo.stored_count().await.unwrap(), 2);
+        let latest = repo.latest_stored(Provider::Nist).await.unwrap().unwrap();
+        assert_eq!(latest.pulse_index, 1187002);
+        assert!(repo.latest_stored(Provider::Chile).await.unwrap().is_none());
+        let id = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 1187001,
+        };
+        assert!(repo.stored_pulse(id).await.unwrap().is_some());
+        let id = PulseId {
+            provider: Provider::Drand,
+            ..id
+        };
+        assert!(repo.stored_pulse(id).await.unwrap().is_none());
+
+        let status = repo.status();
+        assert_eq!((status.queued_bits, status.fetch_errors), (Some(0), 0));
+        assert!(status.last_error.is_none());
+        repo.record_error(
+            Provider::Nist,
+            &NistBeaconRepoErr::InvalidPulse("bad signature".into()),
+        );
+        let status = repo.status();
+        assert_eq!(status.fetch_errors, 1);
+        assert!(status.last_error.unwrap().1.starts_with("NIST: "));
+    }
+
+    /// Serve `body` as JSON on a local port until the test ends.
+    fn serve(body: &'static str) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        format!("http://{addr}/public/latest")
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let down = format!("http://{}/pulse/last", closed.local_addr().unwrap());
+        drop(closed);
+        let beacon = |provider: &str, url: &str| -> Beacon {
+            serde_json::from_value(serde_json::json!({ "provider": provider, "url": url })).unwrap()
+        };
+        let drand = serve(include_str!("fixtures/drand_round_4321001.json"));
+        let repo = NistBeaconRepo::new(db, vec![beacon("nist", &down), beacon("drand", &drand)]);
+
+        let (id, _, _) = repo.get_new_rand().await.unwrap();
+        assert_eq!((id.provider, id.pulse_index), (Provider::Drand, 4321001));
+        assert_eq!(repo.status().fetch_errors, 1);
+
+        // NIST is still down and drand has nothing new
+        assert!(matches!(
+            repo.get_new_rand().await,
+            Err(NistBeaconRepoErr::NoNewRand(_))
+        ));
+        assert_eq!(repo.status().fetch_errors, 2);
+    }
+
+    /// Checks the signature, output hash and chain link of real pulses,
+    /// which the fixtures cannot. Run with `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "fetches from beacon.nist.gov"]
+    async fn test_live_nist() {
+        let client = reqwest::Client::new();
+        let beacon = Beacon::new(Provider::Nist);
+        let Pulse::Nist { pulse: last, .. } = beacon.latest(&client).await.unwrap() else {
+            panic!("NIST beacon returned a drand round");
+        };
+        let prev = client
+            .get(format!(
+                "https://beacon.nist.gov/beacon/2.0/chain/{}/pulse/{}",
+                last.chain_index,
+                last.pulse_index - 1
+            ))
+            .send()
+            .await
+            .unwrap()
+            .json::<NistBeaconResponse>()
+            .await
+            .unwrap()
+            .pulse;
+        let certificate = beacon
+            .certificate(&client, &last.certificate_id)
+            .await
+            .unwrap();
+        prev.verify_signature(&certificate).unwrap();
+        last.verify_signature(&certificate).unwrap();
+
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        NistBeaconRepo::store_pulse(nist(prev), &db).await.unwrap();
+        NistBeaconRepo::store_pulse(nist(*last), &db).await.unwrap();
+        assert_eq!(NistRandEntry::find().all(&db).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_provider_migration() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, Some(7)).await.unwrap();
+        NistRandEntry::insert(nist_rand_entry::ActiveModel {
+            provider: ActiveValue::NotSet,
+            chain_index: ActiveValue::set(2),
+            pulse_index: ActiveValue::set(10),
+            uri: ActiveValue::set("https://beacon.nist.gov/beacon/2.0/chain/2/pulse/10".into()),
+            timestamp: ActiveValue::set("2024-05-01T00:00:00Z".into()),
+            output_value: ActiveValue::set(vec![1; N_BYTES]),
+            ..Default::default()
+        })
+        .exec_without_returning(&db)
+        .await
+        .unwrap();
+        Migrator::up(&db, None).await.unwrap();
+
+        let stored = NistRandEntry::find_by_id(("nist".into(), 2, 10))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.output_value, [1; N_BYTES]);
+
+        // Another provider may reuse the same indices
+        let mut other = stored.into_active_model();
+        other.provider = ActiveValue::set("chile".into());
+        NistRandEntry::insert(other).exec(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reject_pulse() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let (a, mut b) = pulses();
+        NistBeaconRepo::store_pulse(nist(a), &db).await.unwrap();
+        let stored = NistRandEntry::find_by_id(("nist".into(), 2, 1187001))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        b.verify_link(&stored).unwrap();
+
+        let mut other = stored.clone();
+        other.output_value[0] ^= 1;
+        assert!(matches!(
+            b.verify_link(&other),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+        let mut other = stored.clone();
+        other.precommitment_value = Some(vec![0; N_BYTES]);
+        assert!(matches!(
+            b.verify_link(&other),
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+
+        b.time_stamp = "2024-05-01T12:01:00Z".into();
+        assert!(matches!(
+            NistBeaconRepo::store_pulse(nist(b), &db).await,
+            Err(NistBeaconRepoErr::InvalidPulse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let mut output_value = [0u8; N_BYTES];
+        output_value[0] = 0b1010_0000;
+        output_value[N_BYTES - 1] = 0b0000_0111;
+        let db = test_db_with_pulse(&output_value).await;
+        let repo = NistBeaconRepo::new(db, vec![]);
+
+        let span = |offset, len| BitSpan {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 10,
+            offset,
+            len,
+            drbg: false,
+        };
+        let mut replay = repo.replay(&[span(509, 3), span(0, 4)]).await.unwrap();
+        assert_eq!(replay.remaining(), 7);
+        assert_eq!(replay.uris().len(), 1);
+        assert_eq!(
+            replay.pop_bits(7).await.unwrap(),
+            bitvec![u8, Msb0; 1, 1, 1, 1, 0, 1, 0]
+        );
+        assert!(matches!(
+            replay.pop_bits(1).await,
+            Err(NistBeaconRepoErr::ReplayErr(_))
+        ));
+
+        // 0b111 is out of 0..=5 and is rejected, then 0b101 is drawn
+        let mut replay = repo.replay(&[span(509, 3), span(0, 3)]).await.unwrap();
+        assert_eq!(replay.rand(1, 6).await.unwrap(), 6);
+        assert_eq!(replay.remaining(), 0);
+
+        let missing = BitSpan {
+            pulse_index: 11,
+            ..span(0, 1)
+        };
+        assert!(matches!(
+            repo.replay(&[missing]).await,
+            Err(NistBeaconRepoErr::ReplayErr(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rand_many() {
+        let output_value = [0x5au8; N_BYTES];
+        let db = test_db_with_pulse(&output_value).await;
+        let repo = NistBeaconRepo::new(db, vec![]);
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let id = PulseId {
+            provider: Provider::Nist,
+            chain_index: 2,
+            pulse_index: 10,
+        };
+        repo.add_to_reserve(id, OffsetDateTime::UNIX_EPOCH, output_value);
+
+        let mut draw = repo.draw();
+        let nums = draw.rand_many(40, 1, 6).await.unwrap();
+        assert_eq!(nums.len(), 40);
+        assert!(nums.iter().all(|n| (1..=6).contains(n)));
+        let spans = draw.into_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].offset, spans[0].len % 3), (0, 0));
+        assert_eq!(repo.status().waiting, 0);
+
+        let mut replay = repo.replay(&spans).await.unwrap();
+        let mut replayed = vec![];
+        for _ in 0..40 {
+            replayed.push(replay.rand(1, 6).await.unwrap());
+        }
+        assert_eq!(replayed, nums);
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_any_draw_rand_many() {
+        let db = test_db_with_pulse(&[0; N_BYTES]).await;
+        let repo = Arc::new(NistBeaconRepo::new(db, vec![]));
+        repo.prefetching.store(true, Ordering::Relaxed);
+        let sources = RandomSources::new(SourceKind::Beacon, repo.clone(), 0);
+        let (mut a, mut b) = (
+            sources.draw(SourceKind::Beacon),
+            sources.draw(SourceKind::Beacon),
+        );
+
+        // `a` needs more than one pulse and waits for both, with `b` in line
+        // behind it
+        let (nums, num, ()) = tokio::join!(a.rand_many(200, 1, 6), b.rand(1, 6), async {
+            tokio::task::yield_now().await;
+            for pulse_index in [10, 11] {
+                let id = PulseId {
+                    provider: Provider::Nist,
+                    chain_index: 2,
+                    pulse_index,
+                };
+                repo.add_to_reserve(id, OffsetDateTime::UNIX_EPOCH, [0x5a; N_BYTES]);
+            }
+        });
+        assert_eq!(nums.unwrap().len(), 200);
+        num.unwrap();
+
+        let (a, b) = (a.into_spans().unwrap(), b.into_spans().unwrap());
+        assert_eq!(
+            a.iter()
+                .map(|s| (s.pulse_index, s.offset))
+                .collect::<Vec<_>>(),
+            [(10, 0), (11, 0)]
+        );
+        assert_eq!(a[0].len, 512);
+        assert_eq!(
+            b.iter()
+                .map(|s| (s.pulse_index, s.offset))
+                .collect::<Vec<_>>(),
+            [(11, u64::from(a[1].len))]
+        );
+    }
 }
@@ -0,0 +1,295 @@
+use std::sync::{Arc, Mutex};
+
+use bitvec::{order::Msb0, vec::BitVec};
+use rand::{rngs::OsRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+
+use super::nist_beacon::{BitSource, BitSpan, Draw, NistBeaconRepo, Result};
+
+/// Where the bits of rolls come from.
+pub trait RandomSource {
+    type Draw<'a>: BitSource
+    where
+        Self: 'a;
+
+    /// Start drawing the bits of one roll.
+    fn draw(&self) -> Self::Draw<'_>;
+}
+
+impl RandomSource for NistBeaconRepo {
+    type Draw<'a> = Draw<'a>;
+
+    fn draw(&self) -> Draw<'_> {
+        NistBeaconRepo::draw(self)
+    }
+}
+
+/// `n` bits from the front of random bytes filled in by `fill`.
+fn bits_from(n: usize, fill: impl FnOnce(&mut [u8])) -> BitVec<u8, Msb0> {
+    let mut bytes = vec![0u8; n.div_ceil(8)];
+    fill(&mut bytes);
+    let mut bits = BitVec::from_vec(bytes);
+    bits.truncate(n);
+    bits
+}
+
+/// The operating system's CSPRNG, for when the beacon is down.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    type Draw<'a> = OsRandom;
+
+    fn draw(&self) -> OsRandom {
+        OsRandom
+    }
+}
+
+impl BitSource for OsRandom {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        Ok(bits_from(n, |bytes| OsRng.fill_bytes(bytes)))
+    }
+}
+
+/// A deterministic generator, the same seed giving the same rolls.
+pub struct SeededRandom {
+    rng: Mutex<ChaCha20Rng>,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed).into(),
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    type Draw<'a> = SeededDraw<'a>;
+
+    fn draw(&self) -> SeededDraw<'_> {
+        SeededDraw(self)
+    }
+}
+
+pub struct SeededDraw<'a>(&'a SeededRandom);
+
+impl BitSource for SeededDraw<'_> {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        let mut rng = self.0.rng.lock().expect("seeded rng lock is not poisoned");
+        Ok(bits_from(n, |bytes| rng.fill_bytes(bytes)))
+    }
+}
+
+/// Which [`RandomSource`] rolls draw from, set in `Config` or per guild.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Public randomness beacons, NIST first, verifiable with `/verify`
+    #[default]
+    #[name = "Randomness beacon"]
+    Beacon,
+    /// The beacons, each pulse seeding a ChaCha20 stream so it lasts for
+    /// any number of rolls, still verifiable with `/verify`
+    #[name = "Randomness beacon, stretched"]
+    Stretched,
+    /// The operating system's CSPRNG
+    #[name = "OS random"]
+    Os,
+    /// A generator seeded from `Config`, for testing. Its rolls can be
+    /// predicted, so only `Config` may pick it, not a guild
+    Seeded,
+}
+
+impl SourceKind {
+    /// Stable name stored in the database.
+    pub fn key(self) -> &'static str {
+        match self {
+            SourceKind::Beacon => "beacon",
+            SourceKind::Stretched => "stretched",
+            SourceKind::Os => "os",
+            SourceKind::Seeded => "seeded",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Some(match key {
+            "beacon" => SourceKind::Beacon,
+            "stretched" => SourceKind::Stretched,
+            "os" => SourceKind::Os,
+            "seeded" => SourceKind::Seeded,
+            _ => return None,
+        })
+    }
+
+    /// Whether a guild may pick this source with `/rollsource`.
+    pub fn guild_selectable(self) -> bool {
+        self != SourceKind::Seeded
+    }
+
+    /// Whether rolls from this source can be replayed with `/verify`.
+    pub fn verifiable(self) -> bool {
+        matches!(self, SourceKind::Beacon | SourceKind::Stretched)
+    }
+}
+
+/// Every source the bot can roll from, to pick one per roll.
+pub struct RandomSources {
+    pub default: SourceKind,
+    beacon: Arc<NistBeaconRepo>,
+    os: OsRandom,
+    seeded: SeededRandom,
+}
+
+impl RandomSources {
+    pub fn new(default: SourceKind, beacon: Arc<NistBeaconRepo>, seed: u64) -> Self {
+        Self {
+            default,
+            beacon,
+            os: OsRandom,
+            seeded: SeededRandom::new(seed),
+        }
+    }
+
+    /// Start drawing the bits of one roll from `kind`.
+    pub fn draw(&self, kind: SourceKind) -> AnyDraw<'_> {
+        match kind {
+            SourceKind::Beacon => AnyDraw::Beacon(self.beacon.draw()),
+            SourceKind::Stretched => AnyDraw::Beacon(self.beacon.draw_stretched()),
+            SourceKind::Os => AnyDraw::Os(self.os.draw()),
+            SourceKind::Seeded => AnyDraw::Seeded(self.seeded.draw()),
+        }
+    }
+}
+
+/// A draw from whichever source was picked.
+pub enum AnyDraw<'a> {
+    Beacon(Draw<'a>),
+    Os(OsRandom),
+    Seeded(SeededDraw<'a>),
+}
+
+impl AnyDraw<'_> {
+    /// Where the bits came from, if the source can be replayed.
+    pub fn into_spans(self) -> Option<Vec<BitSpan>> {
+        match self {
+            AnyDraw::Beacon(draw) => Some(draw.into_spans()),
+            AnyDraw::Os(_) | AnyDraw::Seeded(_) => None,
+        }
+    }
+}
+
+impl BitSource for AnyDraw<'_> {
+    async fn pop_bits(&mut self, n: usize) -> Result<BitVec<u8, Msb0>> {
+        match self {
+            AnyDraw::Beacon(draw) => draw.pop_bits(n).await,
+            AnyDraw::Os(draw) => draw.pop_bits(n).await,
+            AnyDraw::Seeded(draw) => draw.pop_bits(n).await,
+        }
+    }
+
+    async fn rand_many(&mut self, n: usize, from: i64, to: i64) -> Result<Vec<i64>> {
+        match self {
+            AnyDraw::Beacon(draw) => draw.rand_many(n, from, to).await,
+            AnyDraw::Os(draw) => draw.rand_many(n, from, to).await,
+            AnyDraw::Seeded(draw) => draw.rand_many(n, from, to).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice;
+
+    use super::{BitSource, OsRandom, RandomSource, SeededRandom};
+
+    #[tokio::test]
+    async fn test_seeded_repeats() {
+        let (a, b) = (SeededRandom::new(42), SeededRandom::new(42));
+        let (mut a, mut b) = (a.draw(), b.draw());
+        for n in [1, 7, 8, 13, 64] {
+            let bits = a.pop_bits(n).await.unwrap();
+            assert_eq!(bits.len(), n);
+            assert_eq!(bits, b.pop_bits(n).await.unwrap());
+        }
+        let other = SeededRandom::new(43);
+        assert_ne!(
+            a.pop_bits(64).await.unwrap(),
+            other.draw().pop_bits(64).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seeded_rolls() {
+        let notation = dice::parse("4d6kh3+1d20").unwrap();
+        let (a, b) = (SeededRandom::new(7), SeededRandom::new(7));
+        for _ in 0..20 {
+            let x = notation.roll(&mut a.draw()).await.unwrap().total;
+            assert_eq!(x, notation.roll(&mut b.draw()).await.unwrap().total);
+            assert!((4..=38).contains(&x));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_os_rand_range() {
+        let mut os = OsRandom;
+        for _ in 0..200 {
+            assert!((-3..=3).contains(&os.rand(-3, 3).await.unwrap()));
+        }
+    }
+
+    /// Chi-square statistic of `samples` draws from `from..=to`.
+    async fn chi_square(src: &mut impl BitSource, from: i64, to: i64, samples: usize) -> f64 {
+        let n = (to - from + 1) as usize;
+        let mut counts = vec![0usize; n];
+        for x in src.rand_many(samples, from, to).await.unwrap() {
+            counts[(x - from) as usize] += 1;
+        }
+        let expected = samples as f64 / n as f64;
+        counts
+            .iter()
+            .map(|&c| (c as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_seeded_uniform() {
+        let seeded = SeededRandom::new(2024);
+        let mut draw = seeded.draw();
+        // Critical values at p = 0.001 for n - 1 degrees of freedom
+        for (from, to, critical) in [(1, 6, 20.52), (0, 7, 24.32), (1, 20, 43.82), (-3, 6, 27.88)] {
+            let chi = chi_square(&mut draw, from, to, 20_000).await;
+            assert!(chi < critical, "{from}..={to}: {chi} >= {critical}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seeded_extremes() {
+        let seeded = SeededRandom::new(5);
+        let mut draw = seeded.draw();
+        let nums = draw.rand_many(1000, i64::MIN, i64::MAX).await.unwrap();
+        let negative = nums.iter().filter(|&&x| x < 0).count();
+        assert!((400..600).contains(&negative));
+
+        for (from, to) in [(i64::MAX - 2, i64::MAX), (i64::MIN, i64::MIN + 4), (-1, 0)] {
+            for x in draw.rand_many(50, from, to).await.unwrap() {
+                assert!((from..=to).contains(&x));
+            }
+        }
+        assert_eq!(draw.rand(7, 7).await.unwrap(), 7);
+        assert_eq!(draw.rand(7, 3).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_rand_many_matches_rand() {
+        let (a, b) = (SeededRandom::new(11), SeededRandom::new(11));
+        let (mut a, mut b) = (a.draw(), b.draw());
+        let many = a.rand_many(100, -50, 49).await.unwrap();
+        let mut one_by_one = vec![];
+        for _ in 0..100 {
+            one_by_one.push(b.rand(-50, 49).await.unwrap());
+        }
+        assert_eq!(many, one_by_one);
+    }
+}
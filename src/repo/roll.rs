@@ -0,0 +1,454 @@
+use entity::{prelude::*, *};
+use sea_orm::{
+    sea_query::{Expr, LikeExpr, OnConflict},
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RollRepoErr {
+    #[error("RollRepoErr/DbErr: {0}")]
+    DbErr(#[from] sea_orm::DbErr),
+    #[error("RollRepoErr/TimeFmtErr: {0}")]
+    TimeFmtErr(#[from] time::error::Format),
+}
+
+pub type Result<T, E = RollRepoErr> = std::result::Result<T, E>;
+
+/// A roll whose result was kept out of the channel, to be revealed later.
+pub struct NewHiddenRoll {
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub gm_id: Option<u64>,
+    pub visibility: String,
+    pub title: String,
+    pub description: String,
+    pub colour: u32,
+    pub footer: Option<String>,
+}
+
+/// One row of a `/roll`, as it is kept in the roll log.
+pub struct NewRollLog {
+    pub guild_id: Option<u64>,
+    pub channel_id: u64,
+    pub user_id: u64,
+    pub visibility: String,
+    pub notation: String,
+    pub label: Option<String>,
+    /// The individual dice, as JSON
+    pub dice: String,
+    pub total: i64,
+    /// The beacon bits the roll consumed, as JSON
+    pub draws: Option<String>,
+    /// Key of the random source the roll drew from
+    pub source: String,
+    /// Who a GM-only or blind roll was sent to
+    pub gm_id: Option<u64>,
+}
+
+/// Which rolls of the log to look up, and on whose behalf.
+#[derive(Debug, Default, Clone)]
+pub struct RollLogFilter {
+    pub guild_id: Option<u64>,
+    pub channel_id: Option<u64>,
+    pub user_id: Option<u64>,
+    /// Part of the notation, matched anywhere
+    pub notation: Option<String>,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    /// Hidden rolls are only listed to whoever rolled them, and blind ones
+    /// not even to them, but GM-only and blind rolls are to their GM
+    pub viewer_id: u64,
+}
+
+impl RollLogFilter {
+    fn condition(&self) -> Result<Condition> {
+        let mut cond = Condition::all().add(
+            Condition::any()
+                .add(roll_log::Column::Visibility.eq("public"))
+                .add(
+                    Condition::all()
+                        .add(roll_log::Column::UserId.eq(self.viewer_id as i64))
+                        .add(roll_log::Column::Visibility.ne("blind")),
+                )
+                .add(
+                    Condition::all()
+                        .add(roll_log::Column::GmId.eq(self.viewer_id as i64))
+                        .add(roll_log::Column::Visibility.is_in(["gm", "blind"])),
+                ),
+        );
+        if let Some(id) = self.guild_id {
+            cond = cond.add(roll_log::Column::GuildId.eq(id as i64));
+        }
+        if let Some(id) = self.channel_id {
+            cond = cond.add(roll_log::Column::ChannelId.eq(id as i64));
+        }
+        if let Some(id) = self.user_id {
+            cond = cond.add(roll_log::Column::UserId.eq(id as i64));
+        }
+        if let Some(notation) = &self.notation {
+            // Matched literally, `%` and `_` included
+            let escaped = notation
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            cond = cond.add(
+                Expr::col((roll_log::Entity, roll_log::Column::Notation))
+                    .like(LikeExpr::new(format!("%{escaped}%")).escape('\\')),
+            );
+        }
+        if let Some(since) = self.since {
+            cond = cond.add(roll_log::Column::CreatedAt.gte(format_timestamp(since)?));
+        }
+        if let Some(until) = self.until {
+            cond = cond.add(roll_log::Column::CreatedAt.lt(format_timestamp(until)?));
+        }
+        Ok(cond)
+    }
+}
+
+/// RFC 3339 in UTC to the second, so that timestamps compare as strings.
+fn format_timestamp(t: OffsetDateTime) -> Result<String> {
+    let t = t
+        .to_offset(UtcOffset::UTC)
+        .replace_nanosecond(0)
+        .expect("0 is a valid nanosecond");
+    Ok(t.format(&Rfc3339)?)
+}
+
+pub struct RollRepo {
+    db: DatabaseConnection,
+}
+
+impl RollRepo {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn channel_setting(&self, channel_id: u64) -> Result<Option<channel_setting::Model>> {
+        Ok(ChannelSetting::find_by_id(channel_id as i64)
+            .one(&self.db)
+            .await?)
+    }
+
+    /// Set the default visibility of `/roll` in a channel, `None` to unset.
+    pub async fn set_channel_visibility(
+        &self,
+        channel_id: u64,
+        visibility: Option<String>,
+    ) -> Result<()> {
+        let setting = channel_setting::ActiveModel {
+            channel_id: ActiveValue::set(channel_id as i64),
+            roll_visibility: ActiveValue::set(visibility),
+            ..Default::default()
+        };
+        ChannelSetting::insert(setting)
+            .on_conflict(
+                OnConflict::column(channel_setting::Column::ChannelId)
+                    .update_column(channel_setting::Column::RollVisibility)
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Set who receives GM-only and blind rolls in a channel, `None` to unset.
+    pub async fn set_channel_gm(&self, channel_id: u64, gm_id: Option<u64>) -> Result<()> {
+        let setting = channel_setting::ActiveModel {
+            channel_id: ActiveValue::set(channel_id as i64),
+            gm_id: ActiveValue::set(gm_id.map(|id| id as i64)),
+            ..Default::default()
+        };
+        ChannelSetting::insert(setting)
+            .on_conflict(
+                OnConflict::column(channel_setting::Column::ChannelId)
+                    .update_column(channel_setting::Column::GmId)
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn guild_setting(&self, guild_id: u64) -> Result<Option<guild_setting::Model>> {
+        Ok(GuildSetting::find_by_id(guild_id as i64)
+            .one(&self.db)
+            .await?)
+    }
+
+    /// Set the random source of a guild, `None` to use the bot's default.
+    pub async fn set_guild_source(&self, guild_id: u64, source: Option<String>) -> Result<()> {
+        let setting = guild_setting::ActiveModel {
+            guild_id: ActiveValue::set(guild_id as i64),
+            random_source: ActiveValue::set(source),
+        };
+        GuildSetting::insert(setting)
+            .on_conflict(
+                OnConflict::column(guild_setting::Column::GuildId)
+                    .update_column(guild_setting::Column::RandomSource)
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_hidden_roll(&self, roll: NewHiddenRoll) -> Result<i32> {
+        let created_at = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let new_store = hidden_roll::ActiveModel {
+            guild_id: ActiveValue::set(roll.guild_id.map(|id| id as i64)),
+            channel_id: ActiveValue::set(roll.channel_id as i64),
+            user_id: ActiveValue::set(roll.user_id as i64),
+            gm_id: ActiveValue::set(roll.gm_id.map(|id| id as i64)),
+            visibility: ActiveValue::set(roll.visibility),
+            title: ActiveValue::set(roll.title),
+            description: ActiveValue::set(roll.description),
+            colour: ActiveValue::set(roll.colour as i32),
+            created_at: ActiveValue::set(created_at),
+            revealed: ActiveValue::set(false),
+            footer: ActiveValue::set(roll.footer),
+            ..Default::default()
+        };
+        let res = HiddenRoll::insert(new_store).exec(&self.db).await?;
+        Ok(res.last_insert_id)
+    }
+
+    pub async fn hidden_roll(&self, id: i32) -> Result<Option<hidden_roll::Model>> {
+        Ok(HiddenRoll::find_by_id(id).one(&self.db).await?)
+    }
+
+    /// Mark a hidden roll as revealed, returning `false` if it already was.
+    ///
+    /// Checked in the same statement, so two clicks can't both reveal it.
+    pub async fn mark_revealed(&self, id: i32) -> Result<bool> {
+        let res = HiddenRoll::update_many()
+            .col_expr(hidden_roll::Column::Revealed, true.into())
+            .filter(hidden_roll::Column::Id.eq(id))
+            .filter(hidden_roll::Column::Revealed.eq(false))
+            .exec(&self.db)
+            .await?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Log the rows of a roll, returning their ids in the same order.
+    pub async fn log_rolls(&self, rolls: Vec<NewRollLog>) -> Result<Vec<i32>> {
+        let created_at = format_timestamp(OffsetDateTime::now_utc())?;
+        let txn = self.db.begin().await?;
+        let mut ids = Vec::with_capacity(rolls.len());
+        for r in rolls {
+            let new_store = roll_log::ActiveModel {
+                guild_id: ActiveValue::set(r.guild_id.map(|id| id as i64)),
+                channel_id: ActiveValue::set(r.channel_id as i64),
+                user_id: ActiveValue::set(r.user_id as i64),
+                visibility: ActiveValue::set(r.visibility),
+                notation: ActiveValue::set(r.notation),
+                label: ActiveValue::set(r.label),
+                dice: ActiveValue::set(r.dice),
+                total: ActiveValue::set(r.total),
+                created_at: ActiveValue::set(created_at.clone()),
+                draws: ActiveValue::set(r.draws),
+                source: ActiveValue::set(Some(r.source)),
+                gm_id: ActiveValue::set(r.gm_id.map(|id| id as i64)),
+                ..Default::default()
+            };
+            ids.push(RollLog::insert(new_store).exec(&txn).await?.last_insert_id);
+        }
+        txn.commit().await?;
+        Ok(ids)
+    }
+
+    /// A single roll of the log, if `filter` lets it through.
+    pub async fn roll_log_entry(
+        &self,
+        id: i32,
+        filter: &RollLogFilter,
+    ) -> Result<Option<roll_log::Model>> {
+        Ok(RollLog::find_by_id(id)
+            .filter(filter.condition()?)
+            .one(&self.db)
+            .await?)
+    }
+
+    /// One page of the log, newest first, and the number of matching rolls.
+    pub async fn roll_log_page(
+        &self,
+        filter: &RollLogFilter,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<roll_log::Model>, u64)> {
+        let paginator = RollLog::find()
+            .filter(filter.condition()?)
+            .order_by_desc(roll_log::Column::Id)
+            .paginate(&self.db, per_page);
+        let n = paginator.num_items().await?;
+        Ok((paginator.fetch_page(page).await?, n))
+    }
+
+    /// Up to `limit` matching rolls, oldest first.
+    pub async fn roll_log(
+        &self,
+        filter: &RollLogFilter,
+        limit: u64,
+    ) -> Result<Vec<roll_log::Model>> {
+        let mut rolls = RollLog::find()
+            .filter(filter.condition()?)
+            .order_by_desc(roll_log::Column::Id)
+            .limit(limit)
+            .all(&self.db)
+            .await?;
+        rolls.reverse();
+        Ok(rolls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+
+    use super::{NewHiddenRoll, NewRollLog, RollLogFilter, RollRepo};
+
+    async fn repo() -> RollRepo {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        RollRepo::new(db)
+    }
+
+    #[tokio::test]
+    async fn test_channel_setting() {
+        let repo = repo().await;
+        assert_eq!(repo.channel_setting(1).await.unwrap(), None);
+
+        repo.set_channel_gm(1, Some(42)).await.unwrap();
+        repo.set_channel_visibility(1, Some("blind".into()))
+            .await
+            .unwrap();
+        let s = repo.channel_setting(1).await.unwrap().unwrap();
+        assert_eq!(s.gm_id, Some(42));
+        assert_eq!(s.roll_visibility.as_deref(), Some("blind"));
+
+        repo.set_channel_gm(1, None).await.unwrap();
+        let s = repo.channel_setting(1).await.unwrap().unwrap();
+        assert_eq!(s.gm_id, None);
+        assert_eq!(s.roll_visibility.as_deref(), Some("blind"));
+    }
+
+    #[tokio::test]
+    async fn test_guild_setting() {
+        let repo = repo().await;
+        assert_eq!(repo.guild_setting(1).await.unwrap(), None);
+
+        repo.set_guild_source(1, Some("os".into())).await.unwrap();
+        repo.set_guild_source(1, Some("seeded".into()))
+            .await
+            .unwrap();
+        let s = repo.guild_setting(1).await.unwrap().unwrap();
+        assert_eq!(s.random_source.as_deref(), Some("seeded"));
+
+        repo.set_guild_source(1, None).await.unwrap();
+        let s = repo.guild_setting(1).await.unwrap().unwrap();
+        assert_eq!(s.random_source, None);
+    }
+
+    #[tokio::test]
+    async fn test_reveal_once() {
+        let repo = repo().await;
+        let id = repo
+            .save_hidden_roll(NewHiddenRoll {
+                guild_id: None,
+                channel_id: 1,
+                user_id: 2,
+                gm_id: Some(3),
+                visibility: "gm".into(),
+                title: "Stealth".into(),
+                description: "17".into(),
+                colour: 0xaaff00,
+                footer: None,
+            })
+            .await
+            .unwrap();
+
+        let roll = repo.hidden_roll(id).await.unwrap().unwrap();
+        assert_eq!(roll.title, "Stealth");
+        assert!(!roll.revealed);
+
+        assert!(repo.mark_revealed(id).await.unwrap());
+        assert!(!repo.mark_revealed(id).await.unwrap());
+        assert!(repo.hidden_roll(id).await.unwrap().unwrap().revealed);
+    }
+
+    #[tokio::test]
+    async fn test_roll_log_filter() {
+        let repo = repo().await;
+        let roll = |user_id, visibility: &str, notation: &str| NewRollLog {
+            guild_id: Some(1),
+            channel_id: 2,
+            user_id,
+            visibility: visibility.into(),
+            notation: notation.into(),
+            label: None,
+            dice: "[]".into(),
+            total: 7,
+            draws: None,
+            source: "beacon".into(),
+            gm_id: (visibility == "blind").then_some(12),
+        };
+        let ids = repo
+            .log_rolls(vec![
+                roll(10, "public", "1d20 + 5"),
+                roll(10, "self", "1d20"),
+                roll(10, "blind", "1d20"),
+                roll(11, "self", "2d6"),
+                roll(11, "public", "2d6"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 5);
+
+        let filter = RollLogFilter {
+            viewer_id: 10,
+            ..Default::default()
+        };
+        let entry = |i: usize| repo.roll_log_entry(ids[i], &filter);
+        assert_eq!(entry(0).await.unwrap().unwrap().notation, "1d20 + 5");
+        assert!(entry(2).await.unwrap().is_none(), "blind");
+        assert!(entry(3).await.unwrap().is_none(), "someone else's");
+
+        let (page, n) = repo.roll_log_page(&filter, 0, 2).await.unwrap();
+        assert_eq!(n, 3, "others' hidden rolls and blind rolls are excluded");
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0].notation, "2d6", "newest first");
+
+        let gm = RollLogFilter {
+            viewer_id: 12,
+            ..Default::default()
+        };
+        let entry = repo.roll_log_entry(ids[2], &gm).await.unwrap();
+        assert_eq!(entry.unwrap().gm_id, Some(12), "blind rolls to their GM");
+        assert!(repo.roll_log_entry(ids[1], &gm).await.unwrap().is_none());
+
+        let filter = RollLogFilter {
+            notation: Some("1d20".into()),
+            ..filter
+        };
+        let all = repo.roll_log(&filter, 100).await.unwrap();
+        let notations: Vec<_> = all.iter().map(|r| r.notation.as_str()).collect();
+        assert_eq!(notations, ["1d20 + 5", "1d20"]);
+        for notation in ["d_0", "%", "\\"] {
+            let filter = RollLogFilter {
+                notation: Some(notation.into()),
+                ..filter.clone()
+            };
+            assert!(repo.roll_log(&filter, 100).await.unwrap().is_empty());
+        }
+
+        let filter = RollLogFilter {
+            since: Some(time::OffsetDateTime::now_utc() + time::Duration::hours(1)),
+            ..filter
+        };
+        assert!(repo.roll_log(&filter, 100).await.unwrap().is_empty());
+    }
+}
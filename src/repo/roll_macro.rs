@@ -0,0 +1,148 @@
+use entity::{prelude::*, *};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MacroRepoErr {
+    #[error("MacroRepoErr/DbErr: {0}")]
+    DbErr(#[from] sea_orm::DbErr),
+    #[error("MacroRepoErr/TimeFmtErr: {0}")]
+    TimeFmtErr(#[from] time::error::Format),
+}
+
+pub type Result<T, E = MacroRepoErr> = std::result::Result<T, E>;
+
+/// Macros of a user in exactly one scope, a guild or everywhere.
+fn scope(user_id: u64, guild_id: Option<u64>) -> Condition {
+    let cond = Condition::all().add(roll_macro::Column::UserId.eq(user_id as i64));
+    match guild_id {
+        Some(id) => cond.add(roll_macro::Column::GuildId.eq(id as i64)),
+        None => cond.add(roll_macro::Column::GuildId.is_null()),
+    }
+}
+
+pub struct MacroRepo {
+    db: DatabaseConnection,
+}
+
+impl MacroRepo {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Every macro a user can run in a guild (or in DMs with `None`): those
+    /// saved for everywhere and those saved for that guild.
+    pub async fn macros(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+    ) -> Result<Vec<roll_macro::Model>> {
+        let mut guild = Condition::any().add(roll_macro::Column::GuildId.is_null());
+        if let Some(id) = guild_id {
+            guild = guild.add(roll_macro::Column::GuildId.eq(id as i64));
+        }
+        Ok(RollMacro::find()
+            .filter(roll_macro::Column::UserId.eq(user_id as i64))
+            .filter(guild)
+            .order_by_asc(roll_macro::Column::Name)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Save a macro, returning `true` if it replaced one of the same name.
+    pub async fn save_macro(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        name: String,
+        notation: String,
+    ) -> Result<bool> {
+        let txn = self.db.begin().await?;
+        let existing = RollMacro::find()
+            .filter(scope(user_id, guild_id))
+            .filter(roll_macro::Column::Name.eq(&name))
+            .one(&txn)
+            .await?;
+        let replaced = existing.is_some();
+        match existing {
+            Some(m) => {
+                let mut m = m.into_active_model();
+                m.notation = ActiveValue::set(notation);
+                m.update(&txn).await?;
+            }
+            None => {
+                let new_store = roll_macro::ActiveModel {
+                    user_id: ActiveValue::set(user_id as i64),
+                    guild_id: ActiveValue::set(guild_id.map(|id| id as i64)),
+                    name: ActiveValue::set(name),
+                    notation: ActiveValue::set(notation),
+                    created_at: ActiveValue::set(OffsetDateTime::now_utc().format(&Rfc3339)?),
+                    ..Default::default()
+                };
+                RollMacro::insert(new_store).exec(&txn).await?;
+            }
+        }
+        txn.commit().await?;
+        Ok(replaced)
+    }
+
+    /// Delete a macro, returning `false` if there was none.
+    pub async fn delete_macro(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        name: &str,
+    ) -> Result<bool> {
+        let res = RollMacro::delete_many()
+            .filter(scope(user_id, guild_id))
+            .filter(roll_macro::Column::Name.eq(name))
+            .exec(&self.db)
+            .await?;
+        Ok(res.rows_affected > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::{Migrator, MigratorTrait};
+    use sea_orm::Database;
+
+    use super::MacroRepo;
+
+    #[tokio::test]
+    async fn test_macro_scopes() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let repo = MacroRepo::new(db);
+
+        let save = |guild, name: &str, notation: &str| {
+            repo.save_macro(1, guild, name.into(), notation.into())
+        };
+        assert!(!save(None, "sword", "1d20+5").await.unwrap());
+        assert!(save(None, "sword", "1d20+11").await.unwrap());
+        assert!(!save(Some(7), "sword", "1d20+3").await.unwrap());
+        assert!(!save(Some(8), "bow", "1d20+8").await.unwrap());
+
+        let names = |ms: Vec<entity::roll_macro::Model>| {
+            ms.into_iter()
+                .map(|m| (m.name, m.guild_id, m.notation))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(repo.macros(1, None).await.unwrap()),
+            [("sword".into(), None, "1d20+11".into())]
+        );
+        assert_eq!(repo.macros(1, Some(7)).await.unwrap().len(), 2);
+        assert!(repo.macros(2, Some(7)).await.unwrap().is_empty());
+
+        assert!(repo.delete_macro(1, Some(7), "sword").await.unwrap());
+        assert!(!repo.delete_macro(1, Some(7), "sword").await.unwrap());
+        assert_eq!(
+            names(repo.macros(1, Some(7)).await.unwrap()),
+            [("sword".into(), None, "1d20+11".into())]
+        );
+    }
+}